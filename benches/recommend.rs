@@ -0,0 +1,90 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{Local, TimeZone};
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::{runtime::Runtime, sync::RwLock};
+use vibing::{
+    audio_recommender::Recommender,
+    context_sources::Moment,
+    data_collector::{TimeData, Weather},
+    database::Mp3Database,
+    weather_providers::FixedWeather,
+};
+
+const LIBRARY_SIZE: i64 = 50_000;
+
+// every track gets one weather, one daytime and one seasonal vibe, spread evenly
+async fn generated_library(path: &str) -> Mp3Database {
+    let url = format!("sqlite://{path}?mode=rwc");
+    let database = Mp3Database::new(&url).await.unwrap();
+    database.migrate().await.unwrap();
+
+    let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+    sqlx::query(
+        "
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?)
+        INSERT INTO track_pointers (path) SELECT '/bench/track_' || i || '.mp3' FROM n
+        ")
+        .bind(LIBRARY_SIZE)
+        .execute(&pool).await.unwrap();
+
+    for group in ["weather", "daytime", "seasonal"] {
+        sqlx::query(
+            "
+            WITH group_vibes AS (
+                SELECT vb.vibe_id, ROW_NUMBER() OVER (ORDER BY vb.vibe_id) - 1 AS position,
+                       COUNT(*) OVER () AS total
+                FROM vibes AS vb
+                JOIN vibe_groups AS vg ON vg.vibe_group_id = vb.vibe_group_id
+                WHERE vg.name = ?
+            )
+            INSERT OR IGNORE INTO track_vibes (track_id, vibe_id)
+            SELECT tp.track_id, gv.vibe_id
+            FROM track_pointers AS tp
+            JOIN group_vibes AS gv ON gv.position = (tp.track_id * 7919) % gv.total
+            ")
+            .bind(group)
+            .execute(&pool).await.unwrap();
+    }
+
+    database
+}
+
+fn recommend(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("library.sqlite");
+    let database = runtime.block_on(generated_library(path.to_str().unwrap()));
+
+    let mut group = c.benchmark_group("recommend_50k");
+    group.sample_size(10).measurement_time(Duration::from_secs(20));
+
+    group.bench_function("rank_tracks_by_vibes", |b| {
+        b.to_async(&runtime).iter(|| database.rank_tracks_by_vibes(&[("rainy", 3.0), ("night", 2.0), ("winter", 1.0)]))
+    });
+
+    // the old path, one query per vibe and one more per matched track
+    group.bench_function("get_tracks_by_vibes", |b| {
+        b.to_async(&runtime).iter(|| async {
+            for vibe in ["rainy", "night", "winter"] {
+                database.get_tracks_by_vibes(&[vibe]).await.unwrap();
+            }
+        })
+    });
+
+    let recommender = Recommender::new(
+        Arc::new(RwLock::new(database)),
+        TimeData::default(),
+        Arc::new(FixedWeather::new(Weather::Rainy(18.0))),
+    );
+    // a rainy winter night
+    let moment = Moment::new(Local.with_ymd_and_hms(2025, 12, 1, 23, 0, 0).unwrap());
+    group.bench_function("recommender", |b| {
+        b.to_async(&runtime).iter(|| recommender.get_recommendations_at(&moment))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, recommend);
+criterion_main!(benches);
@@ -0,0 +1,2 @@
+-- Delete metadata table
+DROP TABLE track_metadata;
\ No newline at end of file
@@ -0,0 +1,10 @@
+-- tag metadata cached from the audio files
+CREATE TABLE IF NOT EXISTS track_metadata (
+    track_id INTEGER PRIMARY KEY,
+    title TEXT,
+    artist TEXT,
+    album TEXT,
+    duration_secs REAL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (track_id) REFERENCES track_pointers(track_id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Delete track_vibes vibe index
+DROP INDEX track_vibes_by_vibe;
\ No newline at end of file
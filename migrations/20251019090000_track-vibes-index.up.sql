@@ -0,0 +1,2 @@
+-- look up tracks by vibe without scanning track_vibes
+CREATE INDEX IF NOT EXISTS track_vibes_by_vibe ON track_vibes (vibe_id, track_id);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Delete play history
+DROP TABLE play_history;
\ No newline at end of file
@@ -0,0 +1,18 @@
+-- one row per track that started playing
+CREATE TABLE IF NOT EXISTS play_history (
+    play_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    track_id INTEGER NOT NULL,
+    -- unix seconds
+    started_at INTEGER NOT NULL,
+    listened_secs REAL NOT NULL,
+    -- completed, skipped, stopped or rewound
+    outcome TEXT NOT NULL,
+    -- vibe names of the context the track was picked for
+    time_vibe TEXT,
+    season_vibe TEXT,
+    weather_vibe TEXT,
+    FOREIGN KEY (track_id) REFERENCES track_pointers(track_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS play_history_by_track ON play_history (track_id);
+CREATE INDEX IF NOT EXISTS play_history_by_start ON play_history (started_at);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Delete track feedback
+DROP TABLE track_feedback;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- like/dislike/skip feedback, learned on top of the manual track_vibes tagging
+CREATE TABLE IF NOT EXISTS track_feedback (
+    feedback_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    track_id INTEGER NOT NULL,
+    -- like, dislike or skip
+    feedback TEXT NOT NULL,
+    -- unix seconds
+    created_at INTEGER NOT NULL,
+    -- vibe names of the context it was given in, NULL applies to every context
+    time_vibe TEXT,
+    season_vibe TEXT,
+    weather_vibe TEXT,
+    FOREIGN KEY (track_id) REFERENCES track_pointers(track_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS track_feedback_by_track ON track_feedback (track_id);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Revert the weather vibe to its seeded name
+UPDATE vibes SET name = 'hooty' WHERE name = 'hotty';
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- the weather vibe was seeded as hooty while the recommender asks for hotty
+UPDATE vibes SET name = 'hotty' WHERE name = 'hooty';
\ No newline at end of file
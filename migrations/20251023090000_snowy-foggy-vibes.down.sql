@@ -0,0 +1,2 @@
+-- Delete the snowy and foggy vibes
+DELETE FROM vibes WHERE name IN ('snowy', 'foggy');
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- snow and fog have their own weather vibes now
+INSERT INTO vibes (name, vibe_group_id)
+SELECT vibe.name, vg.vibe_group_id
+FROM (SELECT 'snowy' AS name UNION ALL SELECT 'foggy') AS vibe
+JOIN vibe_groups AS vg ON vg.name = 'weather';
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Delete weather cache
+DROP TABLE weather_cache;
\ No newline at end of file
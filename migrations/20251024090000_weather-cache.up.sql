@@ -0,0 +1,10 @@
+-- last good observation of every weather source, so it survives restarts and network outages
+CREATE TABLE IF NOT EXISTS weather_cache (
+    -- provider and location the observation is for
+    source TEXT PRIMARY KEY,
+    -- weather vibe names separated by commas, like rainy,coldy
+    vibes TEXT NOT NULL,
+    temperature REAL NOT NULL,
+    -- unix seconds
+    fetched_at INTEGER NOT NULL
+);
\ No newline at end of file
@@ -1,92 +1,643 @@
-use std::sync::Arc;
+use std::{collections::{HashMap, HashSet}, sync::Arc, time::Duration};
 
+use chrono::{DateTime, Local};
+use rand::seq::SliceRandom;
 use tokio::sync::RwLock;
 
-use crate::{data_collector::{Season, TimeData, TimePeriod, Weather, WeatherData}, database::{Mp3Database, TrackHeader}};
+use crate::{configuration::RecommenderConfig, context_sources::{ContextSource, Moment, SeasonSource, TimeSource, WeatherSource}, data_collector::{forecast_at, Context, TimeData}, database::{Mp3Database, RankedTrack, TrackHeader}, error::VibingError, weather_providers::WeatherProvider};
+
+#[derive(Debug, Clone)]
+pub struct Recommendation {
+    pub track: TrackHeader,
+    pub score: f32,
+    // the context vibes the track was picked for, in the order they were scored
+    pub matched_vibes: Vec<String>,
+}
+
+impl From<RankedTrack> for Recommendation {
+    fn from(ranked: RankedTrack) -> Self {
+        Self { track: ranked.track, score: ranked.score, matched_vibes: ranked.matched_vibes }
+    }
+}
+
+// how far the recommender had to stray from the context, in the order it tries them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FallbackLevel {
+    // the best track carries every vibe of the context
+    Exact,
+    // tracks matching part of the context
+    Partial,
+    // nothing matched, tracks for the neighbouring time periods and seasons
+    Adjacent,
+    // recommender.default_playlist from the config
+    DefaultPlaylist,
+    // the whole library, shuffled
+    Library,
+}
+
+#[derive(Debug, Clone)]
+pub struct Recommendations {
+    pub level: FallbackLevel,
+    pub tracks: Vec<Recommendation>,
+}
+
+impl Recommendations {
+    pub fn into_tracks(self) -> Vec<TrackHeader> {
+        self.tracks.into_iter().map(|recommendation| recommendation.track).collect()
+    }
+}
+
+// a stretch of a planned timeline, with the context expected then and the tracks to fill it
+#[derive(Debug, Clone)]
+pub struct TimelineSlot {
+    pub start: DateTime<Local>,
+    pub context: Context,
+    pub tracks: Vec<TrackHeader>,
+}
+
+// what a track without a known duration is counted as when filling a timeline
+const ASSUMED_TRACK_DURATION: Duration = Duration::from_secs(4 * 60);
 
 pub struct Recommender {
     database: Arc<RwLock<Mp3Database>>,
+    time_data: TimeData,
+    weather_provider: Arc<dyn WeatherProvider>,
+    weights: RecommenderConfig,
+    // scored in this order, weather first so its vibes lead the explanations
+    sources: Vec<Arc<dyn ContextSource>>,
 }
 
 impl Recommender {
-    pub fn new(database: Arc<RwLock<Mp3Database>>) -> Self {
-        Self { database }
+    pub fn new(database: Arc<RwLock<Mp3Database>>, time_data: TimeData, weather_provider: Arc<dyn WeatherProvider>) -> Self {
+        let sources: Vec<Arc<dyn ContextSource>> = vec![
+            Arc::new(WeatherSource::new(weather_provider.clone())),
+            Arc::new(TimeSource::new(time_data.clone())),
+            Arc::new(SeasonSource::new(time_data.clone())),
+        ];
+        Self {
+            database,
+            time_data,
+            weather_provider,
+            weights: RecommenderConfig::default(),
+            sources,
+        }
+    }
+
+    pub fn with_weights(mut self, weights: RecommenderConfig) -> Self {
+        self.weights = weights;
+        self
     }
 
-    pub async fn get_track(&self) -> Vec<TrackHeader> {
-        let time = TimeData::get_time();
-        let season = TimeData::get_season();
-        let weather = WeatherData::get_weather().await;
+    // another signal next to time, season and weather, like the day of the week or a manual mood
+    pub fn with_source(mut self, source: Arc<dyn ContextSource>) -> Self {
+        self.sources.push(source);
+        self
+    }
 
-        let mut filtered_tracks = Vec::new();
+    pub fn get_database(&self) -> Arc<RwLock<Mp3Database>> {
+        self.database.clone()
+    }
 
-        filtered_tracks.append(&mut self.get_tracks_by_weather(weather).await);
-        filtered_tracks.append(&mut self.get_tracks_by_time(time).await);
-        filtered_tracks.append(&mut self.get_tracks_by_season(season).await);
+    pub fn get_time_data(&self) -> &TimeData {
+        &self.time_data
+    }
 
-        Self::get_most_matched_from(&filtered_tracks)
+    pub fn get_weather_provider(&self) -> &dyn WeatherProvider {
+        self.weather_provider.as_ref()
     }
 
-    async fn get_tracks_by_time(&self, time: TimePeriod) -> Vec<TrackHeader> {
-        let time_str = match time {
-            TimePeriod::Dawn(_) => "dawn",
-            TimePeriod::Morning(_) => "morning",
-            TimePeriod::Noon(_) => "noon",
-            TimePeriod::Afternoon(_) => "afternoon",
-            TimePeriod::Evening(_) => "evening",
-            TimePeriod::Dusk(_) => "dusk",
-            TimePeriod::Night(_) => "night",
-        };
+    pub fn get_weights(&self) -> &RecommenderConfig {
+        &self.weights
+    }
+
+    pub fn get_sources(&self) -> &[Arc<dyn ContextSource>] {
+        &self.sources
+    }
+
+    // looked up by name in source_weights, sources left out there keep their default weight
+    pub fn get_source_weight(&self, source: &dyn ContextSource) -> f32 {
+        self.weights.source_weights.get(source.get_name())
+            .copied()
+            .unwrap_or_else(|| source.get_default_weight(&self.weights))
+    }
 
-        self.database
+    // every vibe name a recommendation can ask the database for
+    pub fn get_vibe_names(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.sources.iter()
+            .flat_map(|source| source.get_vibe_names())
+            .filter(|name| seen.insert(name.clone()))
+            .collect()
+    }
+
+    // fails when the vibes table lacks a name the recommender relies on, tracks could never match it
+    pub async fn check_vibes(&self) -> Result<(), VibingError> {
+        let names = self.get_vibe_names();
+        let names: Vec<_> = names.iter().map(String::as_str).collect();
+        let missing = self.database.read().await.get_missing_vibes(&names).await?;
+        if missing.is_empty() { Ok(()) } else { Err(VibingError::MissingVibes(missing)) }
+    }
+
+    pub async fn get_track(&self) -> Result<Vec<TrackHeader>, VibingError> {
+        Ok(self.get_recommendations_at(&Moment::new(Local::now())).await?.into_tracks())
+    }
+
+    // best first, equal scores keep library order so the same context always gives the same queue.
+    // when nothing matches, falls back step by step down to the whole library.
+    // every source puts its vibes forward on its own weight. feedback is looked up for the vibes
+    // holding at least half of their source's share, whatever the weights
+    pub async fn get_recommendations_at(&self, moment: &Moment) -> Result<Recommendations, VibingError> {
+        let mut groups = Vec::new();
+        let mut context_vibes = Vec::new();
+        for source in self.sources.iter().map(Arc::as_ref) {
+            let vibes = source.get_vibes(moment).await?;
+            context_vibes.extend(vibes.iter().filter(|(_, share)| *share >= 0.5).map(|(name, _)| name.clone()));
+            groups.push(Self::weighted(vibes, self.get_source_weight(source)));
+        }
+
+        let weighted_vibes: Vec<_> = groups.iter().flatten().map(|(name, weight)| (name.as_str(), *weight)).collect();
+        let tracks = self.rank(&weighted_vibes, &context_vibes).await?;
+        if let Some(best) = tracks.first() {
+            // exact when the best track matched something in every group that counts
+            let exact = groups.iter()
+                .filter(|group| !group.is_empty())
+                .all(|group| group.iter().any(|(name, _)| best.matched_vibes.contains(name)));
+            let level = if exact { FallbackLevel::Exact } else { FallbackLevel::Partial };
+            return Ok(Recommendations { level, tracks });
+        }
+
+        // neighbours count half as much as the real thing
+        let mut adjacent_vibes = Vec::new();
+        for source in self.sources.iter().map(Arc::as_ref) {
+            let weight = self.get_source_weight(source) / 2.0;
+            adjacent_vibes.extend(Self::weighted(source.get_adjacent_vibes(moment).await?, weight));
+        }
+        let adjacent_vibes: Vec<_> = adjacent_vibes.iter().map(|(name, weight)| (name.as_str(), *weight)).collect();
+        let tracks = self.rank(&adjacent_vibes, &context_vibes).await?;
+        if !tracks.is_empty() {
+            return Ok(Recommendations { level: FallbackLevel::Adjacent, tracks });
+        }
+
+        let database = self.database.read().await;
+        let playlist = database.get_tracks_by_paths(&self.weights.default_playlist).await?;
+        if !playlist.is_empty() {
+            return Ok(Recommendations { level: FallbackLevel::DefaultPlaylist, tracks: Self::unranked(playlist) });
+        }
+
+        let mut library = database.get_all_tracks().await?;
+        library.shuffle(&mut rand::rng());
+        Ok(Recommendations { level: FallbackLevel::Library, tracks: Self::unranked(library) })
+    }
+
+    // the next hours split where the time period, season or forecast weather changes, each stretch
+    // with enough tracks to last it. the current weather holds until the first forecast after `from`
+    pub async fn get_timeline(&self, from: DateTime<Local>, hours: u32) -> Result<Vec<TimelineSlot>, VibingError> {
+        let to = from + chrono::Duration::hours(hours as i64);
+        let (current, forecast) = self.weather_provider.get_weather_and_forecast().await?;
+
+        let mut starts = self.time_data.get_boundaries_between(from, to);
+        starts.extend(forecast.iter().map(|hour| hour.start).filter(|start| (from..to).contains(start)));
+        starts.push(from);
+        starts.sort();
+        starts.dedup();
+
+        let mut slots: Vec<(DateTime<Local>, Context)> = Vec::new();
+        for start in starts {
+            let weather = forecast_at(&forecast, start)
+                .filter(|hour| hour.start > from)
+                .map_or(current, |hour| hour.weather);
+            let context = Context {
+                time: self.time_data.get_time_at(start),
+                season: self.time_data.get_season_at(start),
+                weather,
+            };
+            if slots.last().is_none_or(|(_, last)| !last.same_vibe(&context)) {
+                slots.push((start, context));
+            }
+        }
+
+        let mut timeline = Vec::new();
+        for (i, &(start, context)) in slots.iter().enumerate() {
+            let end = slots.get(i + 1).map_or(to, |(next, _)| *next);
+            let moment = Moment { weather: Some(context.weather), ..Moment::new(start) };
+            let tracks = self.get_recommendations_at(&moment).await?.into_tracks();
+            let tracks = Self::lasting(tracks, (end - start).to_std().unwrap_or_default());
+            timeline.push(TimelineSlot { start, context, tracks });
+        }
+
+        Ok(timeline)
+    }
+
+    // the first tracks that together play for at least the duration
+    fn lasting(tracks: Vec<TrackHeader>, duration: Duration) -> Vec<TrackHeader> {
+        let mut total = Duration::ZERO;
+        tracks.into_iter()
+            .take_while(|track| {
+                let enough = total >= duration;
+                total += track.metadata.duration.unwrap_or(ASSUMED_TRACK_DURATION);
+                !enough
+            })
+            .collect()
+    }
+
+    // scales the shares a source gave by its weight, dropping what no longer counts
+    fn weighted(vibes: Vec<(String, f32)>, weight: f32) -> Vec<(String, f32)> {
+        vibes.into_iter()
+            .map(|(name, share)| (name, share * weight))
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect()
+    }
+
+    fn unranked(tracks: Vec<TrackHeader>) -> Vec<Recommendation> {
+        tracks.into_iter()
+            .map(|track| Recommendation { track, score: 0.0, matched_vibes: Vec::new() })
+            .collect()
+    }
+
+    async fn rank(&self, weighted_vibes: &[(&str, f32)], context_vibes: &[String]) -> Result<Vec<Recommendation>, VibingError> {
+        let recommendations = self.database
             .read().await
-            .get_tracks_by_vibes(&[time_str]).await
-            .unwrap_or(Vec::new())
+            .rank_tracks_by_vibes(weighted_vibes).await?
+            .into_iter()
+            .map(Recommendation::from)
+            .collect();
+        let recommendations = self.apply_feedback(recommendations, context_vibes).await?;
+
+        self.penalise_recent(recommendations, Local::now()).await
+    }
+
+    // scales scores by the affinity learned from feedback in a similar context, in (-1, 1).
+    // one like or dislike moves it by half, more of them approach the bounds
+    async fn apply_feedback(&self, recommendations: Vec<Recommendation>, context_vibes: &[String]) -> Result<Vec<Recommendation>, VibingError> {
+        let tallies: HashMap<_, _> = self.database
+            .read().await
+            .get_feedback_for(context_vibes).await?
+            .into_iter()
+            .map(|tally| (tally.track_id, tally))
+            .collect();
+
+        Ok(recommendations.into_iter()
+            .filter_map(|mut recommendation| {
+                if let Some(tally) = tallies.get(&recommendation.track.id) {
+                    let skips = self.weights.skip_weight * tally.skips;
+                    let affinity = (tally.likes - tally.dislikes - skips) / (tally.likes + tally.dislikes + skips + 1.0);
+                    recommendation.score *= 1.0 + self.weights.feedback_weight * affinity;
+                }
+                (recommendation.score > 0.0).then_some(recommendation)
+            })
+            .collect())
+    }
+
+    // lowers the score of tracks played within the recency window and drops the last few plays,
+    // both read from play_history so they hold across restarts
+    async fn penalise_recent(&self, mut recommendations: Vec<Recommendation>, now: DateTime<Local>) -> Result<Vec<Recommendation>, VibingError> {
+        let database = self.database.read().await;
+        let window = chrono::Duration::seconds(self.weights.recency_window_secs as i64);
+        let last_played: HashMap<_, _> = database.get_last_played_since(now - window).await?.into_iter().collect();
+        let just_played: HashSet<_> = database.get_recent_plays(self.weights.no_repeat_plays as i64).await?
+            .into_iter()
+            .map(|play| play.track_id)
+            .collect();
+
+        let half_life = self.weights.recency_half_life_secs as f32;
+        for recommendation in &mut recommendations {
+            if let Some(played_at) = last_played.get(&recommendation.track.id) {
+                let age = (now - *played_at).num_seconds().max(0) as f32;
+                recommendation.score *= 1.0 - self.weights.recency_penalty * 0.5f32.powf(age / half_life);
+            }
+        }
+        recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.track.id.cmp(&b.track.id)));
+
+        let (repeats, fresh): (Vec<_>, Vec<_>) = recommendations.into_iter()
+            .partition(|recommendation| just_played.contains(&recommendation.track.id));
+
+        Ok(if fresh.is_empty() { repeats } else { fresh })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{configuration::TimePeriodConfig, data_collector::{Season, TimePeriod, Weather, WeatherSet}, database::{Feedback, PlayOutcome, PlayRecord}, weather_providers::{FileWeatherProvider, FixedWeather}};
+
+    async fn recommender_with(weather_provider: Arc<dyn WeatherProvider>) -> Recommender {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        Recommender::new(Arc::new(RwLock::new(database)), TimeData::default(), weather_provider)
+    }
+
+    // mid-month on the hour, the default time periods keep their blends half an hour around each start
+    fn at(month: u32, hour: u32, weather: impl Into<WeatherSet>) -> Moment {
+        let now = Local.with_ymd_and_hms(2025, month, 15, hour, 0, 0).unwrap();
+        Moment { weather: Some(weather.into()), ..Moment::new(now) }
+    }
+
+    fn file_names(tracks: &[TrackHeader]) -> Vec<&str> {
+        tracks.iter().map(|track| track.path.rsplit('/').next().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn recommends_from_recorded_weather() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/weather/01_wttr_light_rain.json");
+        let recommender = recommender_with(Arc::new(FileWeatherProvider::new(path).unwrap())).await;
+
+        let tracks = recommender.get_track().await.unwrap();
+        assert!(file_names(&tracks).contains(&"Rain.mp3"));
+        assert!(file_names(&tracks).contains(&"MorningRain.mp3"));
     }
 
-    async fn get_tracks_by_season(&self, season: Season) -> Vec<TrackHeader> {
-        let season_str = match season {
-            Season::Spring(_) => "spring",
-            Season::Summer(_) => "summer",
-            Season::Autumn(_) => "autumn",
-            Season::Winter(_) => "winter",
+    #[tokio::test]
+    async fn blends_the_periods_around_the_current_boundary() {
+        use chrono::Timelike;
+
+        // morning starts 20 minutes from now, a sixth of the way into the hour-long blend
+        let now = Local::now();
+        let hour = |minutes: u32| ((now.hour() * 60 + now.minute() + minutes) % (24 * 60)) as f32 / 60.0;
+        let periods = TimePeriodConfig {
+            dawn: hour(23 * 60), morning: hour(20), noon: hour(3 * 60), afternoon: hour(6 * 60),
+            dusk: hour(10 * 60), evening: hour(12 * 60), night: hour(15 * 60),
+            blend_minutes: 60.0,
+            ..TimePeriodConfig::default()
         };
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        database.associate_vibe_with_track(4, "dawn").await.unwrap();
+        let recommender = Recommender::new(Arc::new(RwLock::new(database)), TimeData::new(periods), Arc::new(FixedWeather::new(Weather::Default(20.0))));
 
-        self.database
-            .read().await
-            .get_tracks_by_vibes(&[season_str]).await
-            .unwrap_or(Vec::new())
-    }
-
-    async fn get_tracks_by_weather(&self, weather: Weather) -> Vec<TrackHeader> {
-        let weather_str = match weather {
-            Weather::Sunny(_) => "sunny",
-            Weather::Cloudy(_) => "cloudy",
-            Weather::Rainy(_) => "rainy",
-            Weather::Stormy(_) => "stormy",
-            Weather::Windy(_) => "windy",
-            Weather::Coldy(_) => "coldy",
-            Weather::Hotty(_) => "hotty",
-            Weather::Default(_) => "none",
+        // Rain for dawn leads, the morning tracks already have a share
+        let tracks = recommender.get_track().await.unwrap();
+        let names = file_names(&tracks);
+        assert_eq!(names[0], "Rain.mp3");
+        assert!(names.contains(&"Glorious_morning.mp3") && names.contains(&"MorningRain.mp3"), "{names:?}");
+    }
+
+    #[tokio::test]
+    async fn ranks_tracks_matching_more_vibes_first() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Sunny(32.0)))).await;
+
+        let tracks = recommender
+            .get_recommendations_at(&at(7, 15, Weather::Sunny(32.0)))
+            .await
+            .unwrap()
+            .into_tracks();
+        assert_eq!(file_names(&tracks), ["Summertime.mp3", "Ocean.mp3"]);
+    }
+
+    #[tokio::test]
+    async fn explains_scores_with_configured_weights() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Rainy(20.0)))).await;
+
+        let recommendations = recommender
+            .get_recommendations_at(&at(7, 8, Weather::Rainy(20.0)))
+            .await
+            .unwrap();
+        assert_eq!(recommendations.level, FallbackLevel::Partial);
+        let explained: Vec<_> = recommendations.tracks.iter()
+            .map(|r| (file_names(std::slice::from_ref(&r.track))[0], r.score, r.matched_vibes.join("+")))
+            .collect();
+        assert_eq!(explained, [
+            ("MorningRain.mp3", 5.0, "rainy+morning".to_string()),
+            ("Rain.mp3", 3.0, "rainy".to_string()),
+            ("Glorious_morning.mp3", 2.0, "morning".to_string()),
+            ("Ocean.mp3", 1.0, "summer".to_string()),
+            ("Summertime.mp3", 1.0, "summer".to_string()),
+        ]);
+
+        // with the season outweighing everything the summer tracks move up, ties still in library order
+        let recommender = recommender.with_weights(RecommenderConfig { weather_weight: 1.0, time_weight: 0.0, season_weight: 4.0, ..RecommenderConfig::default() });
+        let tracks = recommender
+            .get_recommendations_at(&at(7, 8, Weather::Rainy(20.0)))
+            .await
+            .unwrap()
+            .into_tracks();
+        assert_eq!(file_names(&tracks), ["Ocean.mp3", "Summertime.mp3", "MorningRain.mp3", "Rain.mp3"]);
+    }
+
+    #[tokio::test]
+    async fn weighs_every_source_by_name() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Rainy(20.0)))).await
+            .with_weights(RecommenderConfig { source_weights: HashMap::from([("season".to_string(), 6.0)]), ..RecommenderConfig::default() });
+        let weight_of = |name: &str| {
+            let source = recommender.get_sources().iter().find(|source| source.get_name() == name).unwrap();
+            recommender.get_source_weight(source.as_ref())
         };
+        assert_eq!((weight_of("weather"), weight_of("time"), weight_of("season")), (3.0, 2.0, 6.0));
 
-        self.database
-            .read().await
-            .get_tracks_by_vibes(&[weather_str]).await
-            .unwrap_or(Vec::new())
+        // on a rainy summer morning the season outweighs rain and morning together
+        let tracks = recommender.get_recommendations_at(&at(7, 8, Weather::Rainy(20.0))).await.unwrap().into_tracks();
+        assert_eq!(file_names(&tracks)[..2], ["Ocean.mp3", "Summertime.mp3"]);
+    }
+
+    async fn play(recommender: &Recommender, name: &str, ago: chrono::Duration) {
+        let database = recommender.get_database();
+        let database = database.read().await;
+        let track = database.get_all_tracks().await.unwrap().into_iter().find(|track| track.display_name() == name).unwrap();
+        database.record_play(&PlayRecord {
+            track_id: track.id,
+            started_at: Local::now() - ago,
+            listened: std::time::Duration::from_secs(120),
+            outcome: PlayOutcome::Completed,
+            time: None,
+            season: None,
+            weather: None,
+        }).await.unwrap();
+    }
+
+    fn weights(no_repeat_plays: u32) -> RecommenderConfig {
+        RecommenderConfig { no_repeat_plays, ..RecommenderConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn recently_played_tracks_sink_with_decay() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Rainy(20.0)))).await.with_weights(weights(0));
+        let rainy_morning = async || recommender.get_recommendations_at(&at(10, 8, Weather::Rainy(20.0))).await.map(Recommendations::into_tracks);
+
+        // long ago, outside the window
+        play(&recommender, "MorningRain.mp3", chrono::Duration::hours(7)).await;
+        assert_eq!(file_names(&rainy_morning().await.unwrap()), ["MorningRain.mp3", "Rain.mp3", "Glorious_morning.mp3"]);
+
+        // two half lives ago, 5 * (1 - 0.8 / 4) still ranks first
+        play(&recommender, "MorningRain.mp3", chrono::Duration::hours(2)).await;
+        assert_eq!(file_names(&rainy_morning().await.unwrap()), ["MorningRain.mp3", "Rain.mp3", "Glorious_morning.mp3"]);
+
+        // just now, 5 * 0.2 falls behind the others
+        play(&recommender, "MorningRain.mp3", chrono::Duration::zero()).await;
+        assert_eq!(file_names(&rainy_morning().await.unwrap()), ["Rain.mp3", "Glorious_morning.mp3", "MorningRain.mp3"]);
     }
 
-    fn get_most_matched_from(slice: &[TrackHeader]) -> Vec<TrackHeader> {
-        let mut counts = std::collections::HashMap::new();
-        for track in slice {
-            *counts.entry(track.clone()).or_insert(0) += 1;
+    #[tokio::test]
+    async fn skips_the_last_plays_unless_nothing_else_matches() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Rainy(20.0)))).await.with_weights(weights(2));
+        play(&recommender, "Rain.mp3", chrono::Duration::minutes(10)).await;
+        play(&recommender, "MorningRain.mp3", chrono::Duration::minutes(5)).await;
+
+        let tracks = recommender.get_recommendations_at(&at(10, 8, Weather::Rainy(20.0))).await.unwrap().into_tracks();
+        assert_eq!(file_names(&tracks), ["Glorious_morning.mp3"]);
+
+        let tracks = recommender.get_recommendations_at(&at(10, 6, Weather::Rainy(20.0))).await.unwrap().into_tracks();
+        assert_eq!(file_names(&tracks), ["Rain.mp3", "MorningRain.mp3"]);
+    }
+
+    #[tokio::test]
+    async fn learns_from_feedback_in_context() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Rainy(20.0)))).await.with_weights(weights(0));
+        let rainy_morning = Context { time: TimePeriod::Morning(8.0), season: Season::Autumn(10), weather: Weather::Rainy(20.0).into() };
+        let tracks_for = async |hour: u32| {
+            recommender.get_recommendations_at(&at(10, hour, Weather::Rainy(20.0))).await.map(Recommendations::into_tracks)
+        };
+
+        let database = recommender.get_database();
+        let tracks = database.read().await.get_all_tracks().await.unwrap();
+        let (glorious, morning_rain) = (&tracks[0], &tracks[1]);
+        for _ in 0..4 {
+            database.read().await.record_feedback(morning_rain.id, Feedback::Skip, Some(rainy_morning)).await.unwrap();
         }
+        database.read().await.record_feedback(glorious.id, Feedback::Like, None).await.unwrap();
+
+        assert_eq!(file_names(&tracks_for(8).await.unwrap()), ["Glorious_morning.mp3", "Rain.mp3"]);
+        // the skips were on rainy mornings, on a rainy evening the track only sinks
+        assert_eq!(file_names(&tracks_for(20).await.unwrap()), ["Rain.mp3", "TownNight.mp3", "MorningRain.mp3"]);
+
+        let tagged = database.read().await.get_vibes_for_track(morning_rain.id).await.unwrap();
+        assert_eq!(tagged.iter().map(|vibe| vibe.name.as_str()).collect::<Vec<_>>(), ["rainy", "morning"]);
+    }
+
+    #[tokio::test]
+    async fn falls_back_when_nothing_matches() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Windy(10.0)))).await;
+        let level_and_tracks = |recommendations: Recommendations| {
+            let tracks: Vec<_> = recommendations.tracks.iter().map(|r| r.track.display_name()).collect();
+            (recommendations.level, tracks)
+        };
+
+        let recommendations = recommender.get_recommendations_at(&at(10, 8, Weather::Rainy(15.0))).await.unwrap();
+        assert_eq!(recommendations.level, FallbackLevel::Partial);
+
+        // nothing for dusk in winter wind, the evening next door has TownNight
+        let recommendations = recommender.get_recommendations_at(&at(1, 18, Weather::Windy(10.0))).await.unwrap();
+        assert_eq!(level_and_tracks(recommendations), (FallbackLevel::Adjacent, vec!["TownNight.mp3".to_string()]));
+
+        // nor for the afternoon, next to noon and dusk
+        let recommendations = recommender.get_recommendations_at(&at(1, 15, Weather::Windy(10.0))).await.unwrap();
+        assert_eq!(recommendations.level, FallbackLevel::Library);
+        assert_eq!(recommendations.tracks.len(), 6);
 
-        let mut sorted_tracks: Vec<_> = counts.into_iter().collect();
-        sorted_tracks.sort_by(|a, b| b.1.cmp(&a.1));
+        let ocean = recommender.get_database().read().await.get_all_tracks().await.unwrap()[2].path.clone();
+        let recommender = recommender.with_weights(RecommenderConfig {
+            default_playlist: vec!["/not/in/the/library.mp3".to_string(), ocean],
+            ..RecommenderConfig::default()
+        });
+        let recommendations = recommender.get_recommendations_at(&at(1, 15, Weather::Windy(10.0))).await.unwrap();
+        assert_eq!(level_and_tracks(recommendations), (FallbackLevel::DefaultPlaylist, vec!["Ocean.mp3".to_string()]));
 
-        sorted_tracks.into_iter().map(|(track, _)| track).collect()
+        // seasons left out, MorningRain carries the whole context
+        let recommender = recommender.with_weights(RecommenderConfig { season_weight: 0.0, ..RecommenderConfig::default() });
+        let recommendations = recommender.get_recommendations_at(&at(10, 8, Weather::Rainy(15.0))).await.unwrap();
+        assert_eq!(recommendations.level, FallbackLevel::Exact);
     }
 
-    
+    #[tokio::test]
+    async fn blends_both_periods_around_a_boundary() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let time_data = TimeData::new(TimePeriodConfig { blend_minutes: 60.0, ..TimePeriodConfig::default() });
+        let recommender = Recommender::new(Arc::new(RwLock::new(database)), time_data, Arc::new(FixedWeather::new(Weather::Default(20.0))));
+        let at = |month: u32, hour: u32, minute: u32| Moment::new(Local.with_ymd_and_hms(2025, month, 15, hour, minute, 0).unwrap());
+        let score_of = |recommendations: &Recommendations, name: &str| {
+            recommendations.tracks.iter().find(|r| r.track.display_name() == name).map(|r| r.score)
+        };
+
+        // a quarter past the start of the evening, three quarters of it against a quarter of dusk
+        let recommendations = recommender.get_recommendations_at(&at(7, 19, 15)).await.unwrap();
+        assert_eq!(score_of(&recommendations, "TownNight.mp3"), Some(1.5));
+        assert_eq!(score_of(&recommendations, "Ocean.mp3"), Some(1.0));
+
+        // right on the start of the night
+        let recommendations = recommender.get_recommendations_at(&at(1, 22, 0)).await.unwrap();
+        assert_eq!(score_of(&recommendations, "TownNight.mp3"), Some(2.0));
+        assert_eq!(recommendations.level, FallbackLevel::Partial);
+    }
+
+    #[tokio::test]
+    async fn scores_every_weather_vibe_of_the_day() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Default(10.0)))).await;
+        let database = recommender.get_database();
+        database.read().await.associate_vibe_with_track(4, "coldy").await.unwrap();
+
+        let rainy_and_cold = WeatherSet::from_vibes("rainy,coldy", 8.0).unwrap();
+        let recommendations = recommender.get_recommendations_at(&at(1, 23, rainy_and_cold)).await.unwrap();
+        let best = &recommendations.tracks[0];
+        assert_eq!((best.track.display_name(), best.score), ("Rain.mp3".to_string(), 6.0));
+        assert_eq!(best.matched_vibes, ["rainy", "coldy"]);
+    }
+
+    #[tokio::test]
+    async fn checks_the_vibes_it_asks_for() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Default(20.0)))).await;
+        recommender.check_vibes().await.unwrap();
+
+        recommender.get_database().read().await.change_vibe_name("hotty", "hooty").await.unwrap();
+        let err = recommender.check_vibes().await.unwrap_err();
+        assert_eq!(err.to_string(), "vibes missing from the vibes table: hotty");
+    }
+
+    struct Mood(&'static str);
+
+    #[async_trait::async_trait]
+    impl ContextSource for Mood {
+        fn get_name(&self) -> &str {
+            "mood"
+        }
+
+        async fn get_vibes(&self, _moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+            Ok(vec![(self.0.to_string(), 1.0)])
+        }
+
+        fn get_vibe_names(&self) -> Vec<String> {
+            vec![self.0.to_string()]
+        }
+    }
+
+    #[tokio::test]
+    async fn scores_vibes_from_added_sources() {
+        let recommender = recommender_with(Arc::new(FixedWeather::new(Weather::Rainy(20.0)))).await
+            .with_source(Arc::new(Mood("cozy")))
+            .with_weights(RecommenderConfig { source_weights: HashMap::from([("mood".to_string(), 4.0)]), ..weights(0) });
+        let err = recommender.check_vibes().await.unwrap_err();
+        assert_eq!(err.to_string(), "vibes missing from the vibes table: cozy");
+
+        let database = recommender.get_database();
+        database.read().await.add_vibe("cozy", "mood").await.unwrap();
+        database.read().await.associate_vibe_with_track(4, "cozy").await.unwrap();
+        recommender.check_vibes().await.unwrap();
+
+        let recommendations = recommender.get_recommendations_at(&at(10, 8, Weather::Rainy(20.0))).await.unwrap();
+        let best = &recommendations.tracks[0];
+        assert_eq!((best.track.display_name(), best.score), ("Rain.mp3".to_string(), 7.0));
+        assert_eq!(best.matched_vibes, ["rainy", "cozy"]);
+    }
+
+    #[tokio::test]
+    async fn plans_a_timeline_from_the_forecast() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecast/wttr_rain_at_noon.json");
+        let recommender = recommender_with(Arc::new(FileWeatherProvider::new(path).unwrap())).await;
+
+        let from = Local.with_ymd_and_hms(2025, 6, 1, 10, 0, 0).unwrap();
+        let timeline = recommender.get_timeline(from, 4).await.unwrap();
+        let planned: Vec<_> = timeline.iter()
+            .map(|slot| (slot.start.format("%H:%M").to_string(), slot.context.time.vibe_name(), slot.context.weather.vibe_name()))
+            .collect();
+        assert_eq!(planned, [
+            ("10:00".to_string(), "morning", "cloudy".to_string()),
+            ("11:00".to_string(), "noon", "cloudy".to_string()),
+            ("12:00".to_string(), "noon", "rainy".to_string()),
+            ("13:00".to_string(), "afternoon", "rainy".to_string()),
+        ]);
+
+        // an hour of rain at four minutes a track takes all the rainy and summer tracks
+        assert_eq!(file_names(&timeline[2].tracks), ["MorningRain.mp3", "Rain.mp3", "Ocean.mp3", "Summertime.mp3"]);
+        assert_eq!(file_names(&timeline[0].tracks)[0], "Glorious_morning.mp3");
+    }
 }
@@ -1,8 +1,21 @@
-use rodio::{Decoder, OutputStream, OutputStreamBuilder, Sink};
+use rand::seq::SliceRandom;
+use rodio::source::SeekError;
+use rodio::mixer::Mixer;
+use rodio::{ChannelCount, Decoder, OutputStream, OutputStreamBuilder, SampleRate, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use crate::configuration::PlayerConfig;
+use crate::database::{PlayOutcome, TrackHeader};
+use crate::error::VibingError;
+use crate::events::{EventBus, VibingEvent};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum State {
     Playing,
@@ -10,7 +23,7 @@ pub enum State {
     Stopped,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Metadata {
     pub title: Option<String>,
     pub artist: Option<String>,
@@ -18,6 +31,22 @@ pub struct Metadata {
     pub duration: Option<Duration>,
 }
 
+impl Metadata {
+    // reads the tags only, no output stream needed
+    pub fn read_from_path(path: impl AsRef<Path>) -> Result<Self, VibingError> {
+        let tag = audiotags::Tag::new().read_from_path(path)?;
+
+        Ok(Metadata {
+            title: tag.title().map(String::from),
+            artist: tag.artist().map(String::from),
+            album: tag.album_title().map(String::from),
+            duration: tag.duration().map(Duration::from_secs_f64),
+        })
+    }
+}
+
+// a single file on its own sink, it publishes Paused and Resumed only.
+// TrackStarted and TrackEnded come from Player, which knows where a track starts and ends
 pub struct Audio {
     path: PathBuf,
     sink: Sink,
@@ -25,46 +54,42 @@ pub struct Audio {
     state: State,
     playback_start_time: Option<Instant>,
     paused_duration: Duration,
+    events: EventBus,
 }
 
 impl Audio {
-    pub fn new(audio_path: &str) -> Self {
+    pub fn new(audio_path: &str) -> Result<Self, VibingError> {
         let path = PathBuf::from(audio_path);
 
-        let _stream_handle = OutputStreamBuilder::open_default_stream()
-            .expect("cannot open stream");
+        // open and decode the file first, no point grabbing the device for a broken track
+        let file = File::open(&path)?;
+        let source = Decoder::try_from(file)?;
 
-        let sink = Sink::connect_new(&_stream_handle.mixer());
+        let _stream_handle = OutputStreamBuilder::open_default_stream()?;
 
-        let file = File::open(&path)
-            .expect("cannot open file");
-
-        let source = Decoder::try_from(file)
-            .expect("cannot load song");
+        let sink = Sink::connect_new(_stream_handle.mixer());
 
         sink.append(source);
         sink.pause();
 
-        Audio {
+        Ok(Audio {
             path,
             sink,
             _stream_handle,
             state: State::Paused,
             playback_start_time: None,
             paused_duration: Duration::from_secs(0),
-        }
+            events: EventBus::default(),
+        })
     }
 
-    pub fn get_metadata(&self) -> Metadata {
-        let tag = audiotags::Tag::new().read_from_path(&self.path)
-            .expect("cannot get metadata");
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
 
-        Metadata {
-            title: tag.title().map(String::from),
-            artist: tag.artist().map(String::from),
-            album: tag.album_title().map(String::from),
-            duration: tag.duration().map(Duration::from_secs_f64),
-        }
+    pub fn get_metadata(&self) -> Result<Metadata, VibingError> {
+        Metadata::read_from_path(&self.path)
     }
 
     pub fn get_current_state(&self) -> State {
@@ -84,6 +109,7 @@ impl Audio {
             if self.playback_start_time.is_none() {
                 self.playback_start_time = Some(Instant::now());
             }
+            self.events.publish(VibingEvent::Resumed);
         }
     }
 
@@ -94,6 +120,7 @@ impl Audio {
             if let Some(start_time) = self.playback_start_time.take() {
                 self.paused_duration += start_time.elapsed();
             }
+            self.events.publish(VibingEvent::Paused);
         }
     }
 
@@ -103,6 +130,7 @@ impl Audio {
 
     pub fn set_volume(&self, volume: f32) {
         self.sink.set_volume(volume.max(0.0)); // Volume can't be negative
+        self.events.publish(VibingEvent::VolumeChanged(self.sink.volume()));
     }
 
     pub fn get_elapsed_time(&self) -> Duration {
@@ -114,26 +142,828 @@ impl Audio {
         }
     }
 
-    // pub fn set_elapsed_time(&mut self, time: Duration) {
-    //     // This requires recreating the source, which is inefficient but the only way in rodio
-    //     self.sink.clear(); // Remove the old source
-    //     let file = File::open(&self.path)
-    //         .expect("cannot open file");
-    //     let source = Decoder::new(BufReader::new(file))
-    //         .unwrap().skip_duration(time);
+    pub fn seek(&mut self, time: Duration) -> Result<(), VibingError> {
+        // try_seek is a no-op once the track has run out, so that case goes through re-decoding too
+        if self.sink.empty() || self.sink.try_seek(time).is_err() {
+            // the decoder can't seek, start it over and skip up to the position instead
+            let file = File::open(&self.path)?;
+            let source = Decoder::try_from(file)?.skip_duration(time);
+
+            self.sink.clear(); // Remove the old source, this also pauses the sink
+            self.sink.append(source);
+            if self.state == State::Playing {
+                self.sink.play();
+            }
+        }
 
-    //     self.sink.append(source);
+        // Reset time tracking
+        self.paused_duration = time;
+        if self.state == State::Playing {
+            self.playback_start_time = Some(Instant::now());
+        } else {
+            self.playback_start_time = None;
+        }
 
-    //     // Reset time tracking
-    //     self.paused_duration = time;
-    //     if self.state == State::Playing {
-    //         self.playback_start_time = Some(Instant::now());
-    //     } else {
-    //         self.playback_start_time = None;
-    //     }
-    // }
+        Ok(())
+    }
+
+    pub fn seek_relative(&mut self, secs: i64) -> Result<(), VibingError> {
+        let elapsed = self.get_elapsed_time();
+        let offset = Duration::from_secs(secs.unsigned_abs());
+        let time = if secs >= 0 {
+            elapsed + offset
+        } else {
+            elapsed.saturating_sub(offset)
+        };
+
+        self.seek(time)
+    }
 
     pub fn is_end(&self) -> bool {
         self.sink.empty() && self.state != State::Paused
     }
 }
+
+// start decoding the next track this long before the current one ends (or its crossfade starts)
+const PRELOAD_AHEAD: Duration = Duration::from_secs(5);
+// previous() restarts the current track instead when we're further in than this
+const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    // next track picked for the same context
+    Track,
+    // the context changed, the next track comes from a new recommendation
+    ContextChange,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fades {
+    pub fade_in: Duration,
+    pub fade_out: Duration,
+    pub crossfade: Duration,
+    pub context_crossfade: Duration,
+}
+
+impl Fades {
+    pub fn crossfade_for(&self, transition: Transition) -> Duration {
+        match transition {
+            Transition::Track => self.crossfade,
+            Transition::ContextChange => self.context_crossfade,
+        }
+    }
+}
+
+impl From<&PlayerConfig> for Fades {
+    fn from(config: &PlayerConfig) -> Self {
+        Self {
+            fade_in: Duration::from_secs_f32(config.fade_in_secs),
+            fade_out: Duration::from_secs_f32(config.fade_out_secs),
+            crossfade: Duration::from_secs_f32(config.crossfade_secs),
+            context_crossfade: Duration::from_secs_f32(config.context_crossfade_secs),
+        }
+    }
+}
+
+// shared between the player and a playing source so the gain can be ramped sample by sample
+pub struct FadeControl {
+    target: AtomicU32,
+    ramp_ms: AtomicU64,
+    skipped: AtomicBool,
+}
+
+impl FadeControl {
+    fn new(gain: f32) -> Arc<Self> {
+        Arc::new(Self { target: AtomicU32::new(gain.to_bits()), ramp_ms: AtomicU64::new(0), skipped: AtomicBool::new(false) })
+    }
+
+    // ends the source at the next sample, the sink moves on to whatever comes after it
+    pub fn skip(&self) {
+        self.skipped.store(true, Ordering::Relaxed);
+    }
+
+    // `duration` is the time a full 0 -> 1 ramp takes
+    pub fn fade_to(&self, gain: f32, duration: Duration) {
+        self.ramp_ms.store(duration.as_millis() as u64, Ordering::Relaxed);
+        self.target.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+pub struct Fade<S> {
+    inner: S,
+    control: Arc<FadeControl>,
+    gain: f32,
+}
+
+impl<S: Source> Fade<S> {
+    pub fn new(inner: S, control: Arc<FadeControl>) -> Self {
+        let gain = f32::from_bits(control.target.load(Ordering::Relaxed));
+        Self { inner, control, gain }
+    }
+}
+
+impl<S: Source> Iterator for Fade<S> {
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.control.skipped.load(Ordering::Relaxed) {
+            return None;
+        }
+        let sample = self.inner.next()?;
+
+        let target = f32::from_bits(self.control.target.load(Ordering::Relaxed));
+        if self.gain != target {
+            let ramp_ms = self.control.ramp_ms.load(Ordering::Relaxed);
+            let samples_per_ms = self.inner.sample_rate() as f32 * self.inner.channels() as f32 / 1000.0;
+            let step = if ramp_ms == 0 { 1.0 } else { 1.0 / (ramp_ms as f32 * samples_per_ms) };
+            self.gain = if self.gain < target {
+                (self.gain + step).min(target)
+            } else {
+                (self.gain - step).max(target)
+            };
+        }
+
+        Some(sample * self.gain)
+    }
+}
+
+impl<S: Source> Source for Fade<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        if self.control.skipped.load(Ordering::Relaxed) {
+            return Some(0);
+        }
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+struct Loaded {
+    track: TrackHeader,
+    duration: Option<Duration>,
+    deck: usize,
+    fade: Arc<FadeControl>,
+    crossfade: Duration,
+    started: bool,
+}
+
+// one output stream for a whole listening session. Tracks are appended to the playing deck
+// shortly before the current one ends so there is no gap at the boundary, crossfades load the
+// next track on the other deck and ramp both gains.
+pub struct Player {
+    decks: [Sink; 2],
+    // None when the decks play into a mixer someone else owns
+    _stream_handle: Option<OutputStream>,
+    fades: Fades,
+    state: State,
+    // pause()/stop() waiting for the fade-out to finish, the bool tells if it was a stop
+    fading_out: Option<(Instant, bool)>,
+    current: Option<Loaded>,
+    preloaded: Option<Loaded>,
+    next_transition: Transition,
+    queue: VecDeque<TrackHeader>,
+    history: Vec<TrackHeader>,
+    events: EventBus,
+}
+
+impl Player {
+    pub fn new(fades: Fades) -> Result<Self, VibingError> {
+        let stream_handle = OutputStreamBuilder::open_default_stream()?;
+        let mut player = Self::with_mixer(stream_handle.mixer(), fades);
+        player._stream_handle = Some(stream_handle);
+
+        Ok(player)
+    }
+
+    pub fn with_mixer(mixer: &Mixer, fades: Fades) -> Self {
+        let decks = [Sink::connect_new(mixer), Sink::connect_new(mixer)];
+        for deck in &decks {
+            deck.pause();
+        }
+
+        Player {
+            decks,
+            _stream_handle: None,
+            fades,
+            state: State::Paused,
+            fading_out: None,
+            current: None,
+            preloaded: None,
+            next_transition: Transition::Track,
+            queue: VecDeque::new(),
+            history: Vec::new(),
+            events: EventBus::default(),
+        }
+    }
+
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn get_current_track(&self) -> Option<&TrackHeader> {
+        self.current.as_ref().map(|loaded| &loaded.track)
+    }
+
+    // tracks that haven't started yet, in play order
+    pub fn get_queue(&self) -> Vec<&TrackHeader> {
+        self.preloaded.iter().map(|loaded| &loaded.track)
+            .chain(self.queue.iter())
+            .collect()
+    }
+
+    pub fn get_history(&self) -> &[TrackHeader] {
+        &self.history
+    }
+
+    pub fn get_current_state(&self) -> State {
+        if self.current.is_none() {
+            State::Stopped
+        } else {
+            self.state.clone()
+        }
+    }
+
+    // how the next track should come in, reset to Transition::Track once it's loaded
+    pub fn set_next_transition(&mut self, transition: Transition) {
+        self.next_transition = transition;
+    }
+
+    pub fn play(&mut self) {
+        if self.state == State::Playing {
+            return;
+        }
+        self.state = State::Playing;
+        self.fading_out = None;
+        self.events.publish(VibingEvent::Resumed);
+
+        let crossfading = self.preloaded.as_ref().is_some_and(|next| next.started && next.deck != self.current_deck());
+        if let Some(current) = &self.current {
+            self.decks[current.deck].play();
+            current.fade.fade_to(if crossfading { 0.0 } else { 1.0 }, self.fades.fade_in);
+        }
+        if let Some(next) = self.preloaded.as_ref().filter(|next| next.started) {
+            self.decks[next.deck].play();
+            next.fade.fade_to(1.0, self.fades.fade_in);
+        }
+        if self.current.is_none() {
+            self.update();
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if self.state != State::Playing {
+            return;
+        }
+        self.state = State::Paused;
+        self.fade_out(false);
+        self.events.publish(VibingEvent::Paused);
+    }
+
+    // fades out, then drops the loaded tracks back into the queue so play() starts them over
+    pub fn stop(&mut self) {
+        if self.state != State::Stopped {
+            self.events.publish(VibingEvent::Stopped);
+        }
+        self.state = State::Stopped;
+        self.fade_out(true);
+    }
+
+    pub fn get_volume(&self) -> f32 {
+        self.decks[0].volume()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        for deck in &self.decks {
+            deck.set_volume(volume.max(0.0)); // Volume can't be negative
+        }
+        self.events.publish(VibingEvent::VolumeChanged(self.get_volume()));
+    }
+
+    pub fn get_elapsed_time(&self) -> Duration {
+        self.decks[self.current_deck()].get_pos()
+    }
+
+    pub fn enqueue(&mut self, track: TrackHeader) {
+        self.queue.push_back(track);
+        self.update();
+    }
+
+    pub fn enqueue_all(&mut self, tracks: impl IntoIterator<Item = TrackHeader>) {
+        self.queue.extend(tracks);
+        self.update();
+    }
+
+    // drops everything that hasn't started yet, the current track keeps playing
+    pub fn clear(&mut self) {
+        self.unload_preloaded();
+        self.queue.clear();
+    }
+
+    pub fn shuffle(&mut self) {
+        self.unload_preloaded();
+        self.queue.make_contiguous().shuffle(&mut rand::rng());
+    }
+
+    pub fn next(&mut self) {
+        if let Some(preloaded) = self.preloaded.take() {
+            self.queue.push_front(preloaded.track);
+        }
+        let listened = self.get_elapsed_time();
+        if let Some(current) = self.current.take() {
+            self.track_ended(&current.track, listened, PlayOutcome::Skipped);
+            self.history.push(current.track);
+        }
+        self.clear_decks();
+        self.update();
+    }
+
+    pub fn previous(&mut self) {
+        if self.get_elapsed_time() > RESTART_THRESHOLD || self.history.is_empty() {
+            if self.decks[self.current_deck()].try_seek(Duration::ZERO).is_err() {
+                self.restart_current();
+            }
+            return;
+        }
+
+        if let Some(preloaded) = self.preloaded.take() {
+            self.queue.push_front(preloaded.track);
+        }
+        let listened = self.get_elapsed_time();
+        if let Some(current) = self.current.take() {
+            self.track_ended(&current.track, listened, PlayOutcome::Rewound);
+            self.queue.push_front(current.track);
+        }
+        if let Some(previous) = self.history.pop() {
+            self.queue.push_front(previous);
+        }
+
+        self.clear_decks();
+        self.update();
+    }
+
+    // call regularly (a few times a second) to follow track boundaries, fades and crossfades
+    pub fn update(&mut self) {
+        if let Some((done_at, stop)) = self.fading_out
+            && Instant::now() >= done_at
+        {
+            self.fading_out = None;
+            for deck in &self.decks {
+                deck.pause();
+            }
+            if stop {
+                self.unload_all();
+            }
+        }
+
+        if let Some(current) = &self.current {
+            let deck = &self.decks[current.deck];
+            let finished = match &self.preloaded {
+                Some(next) if next.deck == current.deck => deck.len() < 2,
+                _ => deck.empty(),
+            };
+            if finished {
+                self.advance();
+            }
+        }
+
+        if self.current.is_none() && self.state != State::Stopped {
+            let deck = self.current_deck();
+            self.current = self.load_next(deck, 0.0);
+            if let Some(current) = &self.current {
+                current.fade.fade_to(1.0, self.fades.fade_in);
+                if self.state == State::Playing {
+                    self.decks[deck].play();
+                }
+                self.events.publish(VibingEvent::TrackStarted(current.track.clone()));
+            }
+        }
+
+        let remaining = self.current.as_ref().and_then(|current| current.duration)
+            .map(|duration| duration.saturating_sub(self.get_elapsed_time()));
+
+        if self.preloaded.is_none() && self.current.is_some() {
+            let lead = self.fades.crossfade_for(self.next_transition) + PRELOAD_AHEAD;
+            if remaining.is_none_or(|remaining| remaining <= lead) {
+                self.preload();
+            }
+        }
+
+        let start_crossfade = self.preloaded.as_ref()
+            .is_some_and(|next| !next.started && remaining.is_some_and(|remaining| remaining <= next.crossfade));
+        if start_crossfade && self.state == State::Playing && self.fading_out.is_none() {
+            self.start_preloaded();
+        }
+    }
+
+    fn current_deck(&self) -> usize {
+        self.current.as_ref().map_or(0, |current| current.deck)
+    }
+
+    fn fade_out(&mut self, stop: bool) {
+        if self.fades.fade_out.is_zero() || self.current.is_none() {
+            self.fading_out = Some((Instant::now(), stop));
+            self.update();
+            return;
+        }
+
+        for loaded in self.current.iter().chain(self.preloaded.iter().filter(|next| next.started)) {
+            loaded.fade.fade_to(0.0, self.fades.fade_out);
+        }
+        self.fading_out = Some((Instant::now() + self.fades.fade_out, stop));
+    }
+
+    fn advance(&mut self) {
+        if let Some(finished) = self.current.take() {
+            // the deck may already be playing the next track, a finished one was heard in full
+            let listened = finished.duration.unwrap_or_else(|| self.decks[finished.deck].get_pos());
+            self.track_ended(&finished.track, listened, PlayOutcome::Completed);
+            self.history.push(finished.track);
+        }
+        self.current = self.preloaded.take();
+        if let Some(current) = &self.current {
+            self.events.publish(VibingEvent::TrackStarted(current.track.clone()));
+        }
+
+        // the boundary came before update() got a chance to start the crossfade
+        if self.current.as_ref().is_some_and(|current| !current.started) && self.state == State::Playing {
+            self.start_preloaded_current();
+        }
+    }
+
+    fn preload(&mut self) {
+        let Some(current) = &self.current else {
+            return;
+        };
+
+        // without a known duration we can't tell when to start a crossfade, append gaplessly instead
+        let crossfade = if current.duration.is_some() {
+            self.fades.crossfade_for(self.next_transition)
+        } else {
+            Duration::ZERO
+        };
+
+        let preloaded = if crossfade.is_zero() {
+            self.load_next(current.deck, 1.0).map(|mut next| {
+                next.started = true;
+                next
+            })
+        } else {
+            let deck = 1 - current.deck;
+            self.decks[deck].pause();
+            self.load_next(deck, 0.0).map(|mut next| {
+                next.crossfade = crossfade;
+                next
+            })
+        };
+
+        if preloaded.is_some() {
+            self.next_transition = Transition::Track;
+        }
+        self.preloaded = preloaded;
+    }
+
+    fn start_preloaded(&mut self) {
+        let (Some(current), Some(next)) = (&self.current, &mut self.preloaded) else {
+            return;
+        };
+
+        self.decks[next.deck].play();
+        next.fade.fade_to(1.0, next.crossfade);
+        current.fade.fade_to(0.0, next.crossfade);
+        next.started = true;
+    }
+
+    fn start_preloaded_current(&mut self) {
+        if let Some(current) = &mut self.current {
+            self.decks[current.deck].play();
+            current.fade.fade_to(1.0, self.fades.fade_in);
+            current.started = true;
+        }
+    }
+
+    // decode and append the first playable track of the queue, broken ones are dropped
+    fn load_next(&mut self, deck: usize, gain: f32) -> Option<Loaded> {
+        while let Some(track) = self.queue.pop_front() {
+            match Self::decode(&track.path) {
+                Ok(source) => {
+                    let duration = source.total_duration();
+                    let fade = FadeControl::new(gain);
+                    self.decks[deck].append(Fade::new(source, fade.clone()));
+                    return Some(Loaded { track, duration, deck, fade, crossfade: Duration::ZERO, started: false });
+                }
+                Err(e) => eprintln!("skip {:?}: {e}", track.path),
+            }
+        }
+
+        None
+    }
+
+    // a sink can't take a source back out, the preloaded one is skipped so it ends as soon as
+    // it's reached. the current track plays on untouched, even when it shares the deck
+    fn unload_preloaded(&mut self) {
+        let Some(preloaded) = self.preloaded.take() else {
+            return;
+        };
+        preloaded.fade.skip();
+        self.queue.push_front(preloaded.track);
+
+        if preloaded.deck != self.current_deck()
+            && let Some(current) = &self.current
+        {
+            current.fade.fade_to(1.0, self.fades.fade_in);
+        }
+    }
+
+    fn unload_all(&mut self) {
+        if let Some(preloaded) = self.preloaded.take() {
+            self.queue.push_front(preloaded.track);
+        }
+        let listened = self.get_elapsed_time();
+        if let Some(current) = self.current.take() {
+            self.track_ended(&current.track, listened, PlayOutcome::Stopped);
+            self.queue.push_front(current.track);
+        }
+        self.clear_decks();
+    }
+
+    fn track_ended(&self, track: &TrackHeader, listened: Duration, outcome: PlayOutcome) {
+        self.events.publish(VibingEvent::TrackEnded { track: track.clone(), listened, outcome });
+    }
+
+    fn clear_decks(&mut self) {
+        for deck in &self.decks {
+            deck.clear();
+        }
+    }
+
+    // for sources that can't seek back to the start
+    fn restart_current(&mut self) {
+        self.unload_preloaded();
+        let Some(current) = self.current.as_mut() else {
+            return;
+        };
+        let deck = &self.decks[current.deck];
+        deck.clear();
+
+        match Self::decode(&current.track.path) {
+            Ok(source) => {
+                let fade = FadeControl::new(1.0);
+                deck.append(Fade::new(source, fade.clone()));
+                current.fade = fade;
+                if self.state == State::Playing {
+                    deck.play();
+                }
+            }
+            Err(e) => {
+                eprintln!("cannot reload {:?}: {e}", current.track.path);
+                self.advance();
+                self.update();
+            }
+        }
+    }
+
+    fn decode(path: &str) -> Result<Decoder<BufReader<File>>, VibingError> {
+        let file = File::open(path)?;
+        Ok(Decoder::try_from(file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    // pulls the mixer in real time on its own thread, like an output device would, until dropped
+    struct Device(Arc<AtomicBool>);
+
+    impl Drop for Device {
+        fn drop(&mut self) {
+            self.0.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn player(fades: Fades) -> (Player, Device) {
+        let (mixer, mut output) = rodio::mixer::mixer(1, RATE);
+        let running = Arc::new(AtomicBool::new(true));
+        let device = running.clone();
+        std::thread::spawn(move || {
+            while device.load(Ordering::Relaxed) {
+                output.by_ref().take(RATE as usize / 100).for_each(drop);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        (Player::with_mixer(&mixer, fades), Device(running))
+    }
+
+    // mono 16-bit pcm at RATE, a constant level so the decoder has something to play
+    fn track(directory: &Path, name: &str, secs: f32) -> TrackHeader {
+        let samples = (RATE as f32 * secs) as u32;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + samples * 2).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend([1u16, 1].map(u16::to_le_bytes).concat());
+        wav.extend([RATE, RATE * 2].map(u32::to_le_bytes).concat());
+        wav.extend([2u16, 16].map(u16::to_le_bytes).concat());
+        wav.extend(b"data");
+        wav.extend((samples * 2).to_le_bytes());
+        wav.extend((0..samples).flat_map(|_| 8000i16.to_le_bytes()));
+
+        let path = directory.join(name);
+        std::fs::write(&path, wav).unwrap();
+        TrackHeader { id: 0, path: path.to_str().unwrap().to_string(), vibes: Vec::new(), metadata: Metadata::default() }
+    }
+
+    fn names(tracks: &[&TrackHeader]) -> Vec<String> {
+        tracks.iter().map(|track| track.display_name()).collect()
+    }
+
+    fn current_name(player: &Player) -> Option<String> {
+        player.get_current_track().map(TrackHeader::display_name)
+    }
+
+    // keeps calling update() like the scheduler does until the condition holds
+    fn update_until(player: &mut Player, condition: impl Fn(&Player) -> bool) {
+        for _ in 0..500 {
+            player.update();
+            if condition(player) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the player never got there");
+    }
+
+    fn crossfading(player: &Player) -> bool {
+        player.preloaded.as_ref().is_some_and(|next| next.started && next.deck != player.current_deck())
+    }
+
+    fn target(fade: &FadeControl) -> f32 {
+        f32::from_bits(fade.target.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn crossfades_on_the_other_deck() {
+        let library = tempfile::tempdir().unwrap();
+        let fades = Fades { crossfade: Duration::from_millis(300), ..Fades::default() };
+        let (mut player, _device) = player(fades);
+        player.enqueue_all([track(library.path(), "a.wav", 1.0), track(library.path(), "b.wav", 1.0)]);
+        player.play();
+
+        // b waits silent on the second deck until a is about to end
+        assert_eq!(names(&player.get_queue()), ["b.wav"]);
+        assert!(player.decks[1].is_paused());
+
+        update_until(&mut player, crossfading);
+        assert!(!player.decks[0].is_paused() && !player.decks[1].is_paused());
+        assert_eq!(target(&player.current.as_ref().unwrap().fade), 0.0);
+        assert_eq!(target(&player.preloaded.as_ref().unwrap().fade), 1.0);
+
+        update_until(&mut player, |player| player.current_deck() == 1);
+        assert_eq!(current_name(&player).as_deref(), Some("b.wav"));
+        assert_eq!(names(&player.get_history().iter().collect::<Vec<_>>()), ["a.wav"]);
+    }
+
+    #[test]
+    fn tells_a_stop_from_a_pause() {
+        let library = tempfile::tempdir().unwrap();
+        let (mut player, _device) = player(Fades::default());
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        player = player.with_events(events);
+        player.enqueue(track(library.path(), "a.wav", 1.0));
+        player.play();
+
+        player.pause();
+        player.play();
+        player.stop();
+        player.stop();
+        let mut published = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            match event {
+                VibingEvent::Paused => published.push("paused"),
+                VibingEvent::Resumed => published.push("resumed"),
+                VibingEvent::Stopped => published.push("stopped"),
+                _ => {}
+            }
+        }
+        assert_eq!(published, ["resumed", "paused", "resumed", "stopped"]);
+    }
+
+    #[test]
+    fn clearing_during_a_crossfade_brings_the_current_track_back() {
+        let library = tempfile::tempdir().unwrap();
+        let fades = Fades { crossfade: Duration::from_millis(500), ..Fades::default() };
+        let (mut player, _device) = player(fades);
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        player = player.with_events(events);
+        player.enqueue_all([track(library.path(), "a.wav", 1.0), track(library.path(), "b.wav", 1.0)]);
+        player.play();
+
+        update_until(&mut player, crossfading);
+        player.clear();
+        assert!(player.get_queue().is_empty());
+        assert_eq!(target(&player.current.as_ref().unwrap().fade), 1.0);
+
+        update_until(&mut player, |player| player.get_current_track().is_none());
+        let mut started = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let VibingEvent::TrackStarted(track) = event {
+                started.push(track.display_name());
+            }
+        }
+        assert_eq!(started, ["a.wav"]);
+    }
+
+    #[test]
+    fn clearing_a_gapless_preload_leaves_the_current_track_playing() {
+        let library = tempfile::tempdir().unwrap();
+        let (mut player, _device) = player(Fades::default());
+        player.enqueue_all([track(library.path(), "a.wav", 1.0), track(library.path(), "b.wav", 1.0)]);
+        player.play();
+
+        // without a crossfade b is appended behind a on the same deck
+        update_until(&mut player, |player| player.get_elapsed_time() >= Duration::from_millis(300));
+        assert_eq!(player.preloaded.as_ref().map(|next| next.deck), Some(0));
+        let fade = player.current.as_ref().unwrap().fade.clone();
+        let elapsed = player.get_elapsed_time();
+
+        player.clear();
+        assert!(Arc::ptr_eq(&fade, &player.current.as_ref().unwrap().fade));
+        assert!(player.get_elapsed_time() >= elapsed);
+
+        update_until(&mut player, |player| player.get_current_track().is_none());
+        assert_eq!(names(&player.get_history().iter().collect::<Vec<_>>()), ["a.wav"]);
+    }
+
+    #[test]
+    fn previous_goes_back_early_and_restarts_without_history() {
+        let library = tempfile::tempdir().unwrap();
+        let (mut player, _device) = player(Fades::default());
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        player = player.with_events(events);
+        player.enqueue_all([track(library.path(), "a.wav", 2.0), track(library.path(), "b.wav", 2.0)]);
+        player.play();
+
+        update_until(&mut player, |player| player.get_elapsed_time() >= Duration::from_millis(300));
+        player.previous();
+        assert_eq!(current_name(&player).as_deref(), Some("a.wav"));
+        assert!(player.get_elapsed_time() < Duration::from_millis(300));
+
+        player.next();
+        assert_eq!(current_name(&player).as_deref(), Some("b.wav"));
+        player.previous();
+        assert_eq!(current_name(&player).as_deref(), Some("a.wav"));
+        assert_eq!(names(&player.get_queue()), ["b.wav"]);
+        assert!(player.get_history().is_empty());
+
+        // only next() counts as a skip, going back isn't a verdict on the track
+        let mut outcomes = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let VibingEvent::TrackEnded { track, outcome, .. } = event {
+                outcomes.push((track.display_name(), outcome));
+            }
+        }
+        assert_eq!(outcomes, [("a.wav".to_string(), PlayOutcome::Skipped), ("b.wav".to_string(), PlayOutcome::Rewound)]);
+    }
+
+    #[test]
+    fn fade_ramps_gain_over_the_requested_duration() {
+        // mono at 1kHz, one sample per millisecond
+        let source = SamplesBuffer::new(1, 1000, vec![1.0; 3000]);
+        let control = FadeControl::new(0.0);
+        let mut fade = Fade::new(source, control.clone());
+
+        control.fade_to(1.0, Duration::from_secs(1));
+        let samples: Vec<f32> = fade.by_ref().take(1000).collect();
+        assert!((samples[499] - 0.5).abs() < 0.01);
+        assert!((samples[999] - 1.0).abs() < 0.01);
+
+        control.fade_to(0.0, Duration::from_millis(500));
+        let samples: Vec<f32> = fade.take(1000).collect();
+        assert!((samples[249] - 0.5).abs() < 0.01);
+        assert_eq!(samples[999], 0.0);
+    }
+}
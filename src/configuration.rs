@@ -0,0 +1,593 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::data_collector::{Weather, WeatherSet};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read config file: {e}"),
+            ConfigError::Parse(e) => write!(f, "cannot parse config file: {e}"),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub database_url: String,
+    pub weather: WeatherConfig,
+    pub player: PlayerConfig,
+    pub scheduler: SchedulerConfig,
+    pub recommender: RecommenderConfig,
+    pub time_periods: TimePeriodConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherProviderKind {
+    Wttr,
+    OpenMeteo,
+    Fixed,
+    File,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WeatherConfig {
+    pub provider: WeatherProviderKind,
+    // wttr
+    pub host: String,
+    pub city: String,
+    // open_meteo
+    pub open_meteo_host: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    // fixed
+    pub fixed_weather: String,
+    pub fixed_temperature: f32,
+    // file, a recorded response or a directory of them
+    pub replay_path: String,
+    pub thresholds: WeatherThresholds,
+    pub mapping: WeatherMappingConfig,
+    // wttr and open_meteo answers are reused this long before asking again
+    pub cache_ttl_secs: u64,
+    // how old the last good answer may get while the network is down, the weather is none after that
+    pub max_staleness_secs: u64,
+    pub http: HttpConfig,
+}
+
+// every request gets the timeout, timeouts, failed connections, 5xx and 429 are retried after backoff_ms, doubling each time
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub timeout_secs: u64,
+    pub retries: u32,
+    pub backoff_ms: u64,
+}
+
+// extra or replacement entries for the built-in table of provider weather codes.
+// descriptions are used for codes no table knows, case doesn't matter
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WeatherMappingConfig {
+    pub codes: HashMap<u16, String>,
+    pub descriptions: HashMap<String, String>,
+}
+
+// when the measurements add hotty, coldy or windy to the weather the provider describes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WeatherThresholds {
+    pub hot_celsius: f32,
+    // humid air feels hot a few degrees earlier
+    pub humid_hot_celsius: f32,
+    pub humid_percent: f32,
+    pub cold_celsius: f32,
+    pub windy_kmph: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PlayerConfig {
+    pub volume: f32,
+    pub fade_in_secs: f32,
+    pub fade_out_secs: f32,
+    // between tracks picked for the same context
+    pub crossfade_secs: f32,
+    // when the context changed and the next track comes from a new recommendation
+    pub context_crossfade_secs: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub interval_secs: u64,
+    // a forecast change this close starts mixing its weather into the queue
+    pub lookahead_secs: u64,
+}
+
+// how much a matching vibe of each group adds to a track's score
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RecommenderConfig {
+    pub weather_weight: f32,
+    pub time_weight: f32,
+    pub season_weight: f32,
+    // weights of the context sources by name. time, season and weather fall back on the weights
+    // above, any other source on 1.0
+    pub source_weights: HashMap<String, f32>,
+    // plays older than the window don't affect recommendations
+    pub recency_window_secs: u64,
+    // share of the score taken from a track played just now, halving every half life
+    pub recency_penalty: f32,
+    pub recency_half_life_secs: u64,
+    // tracks among the last n plays are left out unless nothing else matches
+    pub no_repeat_plays: u32,
+    // how far learned likes/dislikes move a score, a track the feedback pushes to zero is left out
+    pub feedback_weight: f32,
+    // a skip counts as this much of a dislike
+    pub skip_weight: f32,
+    // track paths played when nothing matches the context or its neighbours
+    pub default_playlist: Vec<String>,
+}
+
+// start hour of each period, night wraps around midnight until dawn.
+// with a location the hours follow the sun and these are only used when it can't be computed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TimePeriodConfig {
+    pub dawn: f32,
+    pub morning: f32,
+    pub noon: f32,
+    pub afternoon: f32,
+    pub dusk: f32,
+    pub evening: f32,
+    pub night: f32,
+    // width of the soft boundary centred on each start hour, where both periods blend
+    pub blend_minutes: f32,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: "sqlite://vibing_library.sqlite".to_string(),
+            weather: WeatherConfig::default(),
+            player: PlayerConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            recommender: RecommenderConfig::default(),
+            time_periods: TimePeriodConfig::default(),
+        }
+    }
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            provider: WeatherProviderKind::Wttr,
+            host: "https://wttr.in".to_string(),
+            city: "Hanoi".to_string(),
+            open_meteo_host: "https://api.open-meteo.com".to_string(),
+            latitude: 21.0285,
+            longitude: 105.8542,
+            fixed_weather: "none".to_string(),
+            fixed_temperature: 25.0,
+            replay_path: String::new(),
+            thresholds: WeatherThresholds::default(),
+            mapping: WeatherMappingConfig::default(),
+            cache_ttl_secs: 15 * 60,
+            max_staleness_secs: 6 * 60 * 60,
+            http: HttpConfig::default(),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 10,
+            retries: 3,
+            backoff_ms: 500,
+        }
+    }
+}
+
+impl Default for WeatherThresholds {
+    fn default() -> Self {
+        Self {
+            hot_celsius: 32.0,
+            humid_hot_celsius: 29.0,
+            humid_percent: 75.0,
+            cold_celsius: 15.0,
+            windy_kmph: 30.0,
+        }
+    }
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            volume: 0.2,
+            fade_in_secs: 1.0,
+            fade_out_secs: 1.0,
+            crossfade_secs: 3.0,
+            context_crossfade_secs: 8.0,
+        }
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self { interval_secs: 300, lookahead_secs: 30 * 60 }
+    }
+}
+
+impl Default for RecommenderConfig {
+    fn default() -> Self {
+        Self {
+            weather_weight: 3.0,
+            time_weight: 2.0,
+            season_weight: 1.0,
+            source_weights: HashMap::new(),
+            recency_window_secs: 6 * 60 * 60,
+            recency_penalty: 0.8,
+            recency_half_life_secs: 60 * 60,
+            no_repeat_plays: 3,
+            feedback_weight: 1.5,
+            skip_weight: 0.5,
+            default_playlist: Vec::new(),
+        }
+    }
+}
+
+impl Default for TimePeriodConfig {
+    fn default() -> Self {
+        Self {
+            dawn: 5.0,
+            morning: 7.0,
+            noon: 11.0,
+            afternoon: 13.0,
+            dusk: 17.0,
+            evening: 19.0,
+            night: 22.0,
+            blend_minutes: 30.0,
+            latitude: None,
+            longitude: None,
+        }
+    }
+}
+
+impl Config {
+    // app_config.json, then .env / environment overrides, then validation
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let mut config = Self::from_json(&content)?;
+        config.apply_env();
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, ConfigError> {
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(content).map_err(ConfigError::Parse)
+    }
+
+    pub fn apply_env(&mut self) {
+        dotenvy::dotenv().ok();
+
+        if let Ok(database_url) = std::env::var("DATABASE_URL") {
+            self.database_url = database_url;
+        }
+        if let Ok(weather_host) = std::env::var("WEATHER_HOST") {
+            self.weather.host = weather_host;
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.database_url.starts_with("sqlite:") {
+            return Err(ConfigError::Invalid(format!(
+                "database_url must be a sqlite url, got {:?}", self.database_url
+            )));
+        }
+
+        self.weather.validate()?;
+
+        if !(0.0..=1.0).contains(&self.player.volume) {
+            return Err(ConfigError::Invalid(format!(
+                "player.volume must be between 0.0 and 1.0, got {}", self.player.volume
+            )));
+        }
+
+        let fades = [
+            ("fade_in_secs", self.player.fade_in_secs),
+            ("fade_out_secs", self.player.fade_out_secs),
+            ("crossfade_secs", self.player.crossfade_secs),
+            ("context_crossfade_secs", self.player.context_crossfade_secs),
+        ];
+        for (name, secs) in fades {
+            if !(0.0..=60.0).contains(&secs) {
+                return Err(ConfigError::Invalid(format!(
+                    "player.{name} must be between 0 and 60 seconds, got {secs}"
+                )));
+            }
+        }
+
+        if self.scheduler.interval_secs == 0 {
+            return Err(ConfigError::Invalid("scheduler.interval_secs must be greater than 0".to_string()));
+        }
+
+        self.recommender.validate()?;
+        self.time_periods.validate()
+    }
+}
+
+impl WeatherConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self.provider {
+            WeatherProviderKind::Wttr => {
+                check_url("weather.host", &self.host)?;
+                if self.city.trim().is_empty() {
+                    return Err(ConfigError::Invalid("weather.city must not be empty".to_string()));
+                }
+            }
+            WeatherProviderKind::OpenMeteo => {
+                check_url("weather.open_meteo_host", &self.open_meteo_host)?;
+                if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+                    return Err(ConfigError::Invalid(format!(
+                        "weather.latitude/longitude out of range, got ({}, {})", self.latitude, self.longitude
+                    )));
+                }
+            }
+            WeatherProviderKind::Fixed => {
+                if WeatherSet::from_vibes(&self.fixed_weather, self.fixed_temperature).is_none() {
+                    return Err(ConfigError::Invalid(format!(
+                        "weather.fixed_weather must be weather vibes separated by commas, got {:?}", self.fixed_weather
+                    )));
+                }
+            }
+            WeatherProviderKind::File => {
+                if self.replay_path.trim().is_empty() {
+                    return Err(ConfigError::Invalid("weather.replay_path must be set for the file provider".to_string()));
+                }
+            }
+        }
+
+        if self.max_staleness_secs < self.cache_ttl_secs {
+            return Err(ConfigError::Invalid(format!(
+                "weather.max_staleness_secs ({}) must not be shorter than weather.cache_ttl_secs ({})",
+                self.max_staleness_secs, self.cache_ttl_secs
+            )));
+        }
+        if self.http.timeout_secs == 0 {
+            return Err(ConfigError::Invalid("weather.http.timeout_secs must be greater than 0".to_string()));
+        }
+
+        self.thresholds.validate()?;
+        self.mapping.validate()
+    }
+}
+
+impl WeatherMappingConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let codes = self.codes.iter().map(|(code, vibe)| (code.to_string(), vibe));
+        let descriptions = self.descriptions.iter().map(|(description, vibe)| (format!("{description:?}"), vibe));
+        for (key, vibe) in codes.chain(descriptions) {
+            if Weather::from_vibe(vibe, 0.0).is_none() {
+                return Err(ConfigError::Invalid(format!(
+                    "weather.mapping maps {key} to {vibe:?}, which is not a weather vibe"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WeatherThresholds {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.cold_celsius < self.humid_hot_celsius && self.humid_hot_celsius <= self.hot_celsius) {
+            return Err(ConfigError::Invalid(format!(
+                "weather.thresholds must keep cold_celsius < humid_hot_celsius <= hot_celsius, got {} / {} / {}",
+                self.cold_celsius, self.humid_hot_celsius, self.hot_celsius
+            )));
+        }
+        if !(0.0..=100.0).contains(&self.humid_percent) {
+            return Err(ConfigError::Invalid(format!(
+                "weather.thresholds.humid_percent must be between 0 and 100, got {}", self.humid_percent
+            )));
+        }
+        if !self.windy_kmph.is_finite() || self.windy_kmph <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "weather.thresholds.windy_kmph must be greater than 0, got {}", self.windy_kmph
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl RecommenderConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let weights = [
+            ("weather_weight", self.weather_weight),
+            ("time_weight", self.time_weight),
+            ("season_weight", self.season_weight),
+            ("feedback_weight", self.feedback_weight),
+            ("skip_weight", self.skip_weight),
+        ];
+        let source_weights = self.source_weights.iter().map(|(name, weight)| (format!("source_weights.{name}"), *weight));
+        for (name, weight) in weights.map(|(name, weight)| (name.to_string(), weight)).into_iter().chain(source_weights) {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(ConfigError::Invalid(format!(
+                    "recommender.{name} must not be negative, got {weight}"
+                )));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.recency_penalty) {
+            return Err(ConfigError::Invalid(format!(
+                "recommender.recency_penalty must be between 0.0 and 1.0, got {}", self.recency_penalty
+            )));
+        }
+        if self.recency_half_life_secs == 0 {
+            return Err(ConfigError::Invalid("recommender.recency_half_life_secs must be greater than 0".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+fn check_url(name: &str, url: &str) -> Result<(), ConfigError> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(ConfigError::Invalid(format!(
+            "{name} must start with http:// or https://, got {url:?}"
+        )));
+    }
+    Ok(())
+}
+
+impl TimePeriodConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match (self.latitude, self.longitude) {
+            (None, None) => {}
+            (Some(latitude), Some(longitude)) if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) => {}
+            (latitude, longitude) => {
+                return Err(ConfigError::Invalid(format!(
+                    "time_periods.latitude/longitude must both be set and in range, got ({latitude:?}, {longitude:?})"
+                )));
+            }
+        }
+
+        let boundaries = [
+            ("dawn", self.dawn),
+            ("morning", self.morning),
+            ("noon", self.noon),
+            ("afternoon", self.afternoon),
+            ("dusk", self.dusk),
+            ("evening", self.evening),
+            ("night", self.night),
+        ];
+
+        for (name, hour) in boundaries {
+            if !(0.0..24.0).contains(&hour) {
+                return Err(ConfigError::Invalid(format!(
+                    "time_periods.{name} must be an hour in [0, 24), got {hour}"
+                )));
+            }
+        }
+
+        for pair in boundaries.windows(2) {
+            let ((prev_name, prev), (name, hour)) = (pair[0], pair[1]);
+            if hour <= prev {
+                return Err(ConfigError::Invalid(format!(
+                    "time_periods.{name} ({hour}) must come after time_periods.{prev_name} ({prev})"
+                )));
+            }
+        }
+
+        // a period has to be longer than the blend or its two soft boundaries would overlap
+        let shortest = boundaries.windows(2)
+            .map(|pair| pair[1].1 - pair[0].1)
+            .fold(24.0 - self.night + self.dawn, f32::min);
+        if !(0.0..=shortest * 60.0).contains(&self.blend_minutes) {
+            return Err(ConfigError::Invalid(format!(
+                "time_periods.blend_minutes must be between 0 and the shortest period ({} minutes), got {}",
+                shortest * 60.0, self.blend_minutes
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_fall_back_to_defaults() {
+        let config = Config::from_json(r#"{ "weather": { "city": "Hue" } }"#).unwrap();
+        assert_eq!(config.weather.city, "Hue");
+        assert_eq!(config.weather.host, "https://wttr.in");
+        assert_eq!(config.player.volume, 0.2);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn keeps_the_cause_of_load_errors() {
+        use std::error::Error;
+
+        let err = Config::load("/no/such/app_config.json").unwrap_err();
+        assert!(err.source().unwrap().downcast_ref::<std::io::Error>().is_some());
+        let err = Config::from_json("{ \"weather\": ").unwrap_err();
+        assert!(err.source().unwrap().downcast_ref::<serde_json::Error>().is_some());
+    }
+
+    #[test]
+    fn rejects_unordered_time_periods() {
+        let config = Config::from_json(r#"{ "time_periods": { "noon": 6.0 } }"#).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid config: time_periods.noon (6) must come after time_periods.morning (7)");
+    }
+
+    #[test]
+    fn rejects_blends_longer_than_a_period() {
+        let config = Config::from_json(r#"{ "time_periods": { "blend_minutes": 90.0 } }"#).unwrap();
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_json(r#"{ "time_periods": { "blend_minutes": 150.0 } }"#).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validates_selected_weather_provider_only() {
+        let config = Config::from_json(r#"{ "weather": { "provider": "fixed", "fixed_weather": "rainy", "host": "" } }"#).unwrap();
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_json(r#"{ "weather": { "provider": "fixed", "fixed_weather": "sunnyish" } }"#).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_mappings_to_unknown_vibes() {
+        let config = Config::from_json(r#"{ "weather": { "mapping": { "codes": { "230": "snowy" }, "descriptions": { "Sandstorm": "windy" } } } }"#).unwrap();
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_json(r#"{ "weather": { "mapping": { "codes": { "230": "slushy" } } } }"#).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid config: weather.mapping maps 230 to \"slushy\", which is not a weather vibe");
+    }
+
+    #[test]
+    fn rejects_crossed_weather_thresholds() {
+        let config = Config::from_json(r#"{ "weather": { "provider": "fixed", "fixed_weather": "rainy,coldy" } }"#).unwrap();
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_json(r#"{ "weather": { "thresholds": { "cold_celsius": 30.0 } } }"#).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid config: weather.thresholds must keep cold_celsius < humid_hot_celsius <= hot_celsius, got 30 / 29 / 32");
+    }
+}
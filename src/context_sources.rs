@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+
+use crate::{configuration::RecommenderConfig, data_collector::{Season, TimeData, TimePeriod, Weather, WeatherSet}, error::VibingError, weather_providers::WeatherProvider};
+
+// what the sources are asked about: an instant, plus what the caller already knows about it.
+// weather is the one it has for then, the weather source asks its provider otherwise.
+// upcoming is a different weather the forecast has coming, with its share growing as it nears
+#[derive(Debug, Clone)]
+pub struct Moment {
+    pub now: DateTime<Local>,
+    pub weather: Option<WeatherSet>,
+    pub upcoming: Option<(WeatherSet, f32)>,
+}
+
+impl Moment {
+    pub fn new(now: DateTime<Local>) -> Self {
+        Self { now, weather: None, upcoming: None }
+    }
+}
+
+// a signal the recommender matches tracks against. every source scores on its weight in
+// recommender.source_weights, by name, falling back on its default weight
+#[async_trait]
+pub trait ContextSource: Send + Sync {
+    fn get_name(&self) -> &str;
+
+    fn get_default_weight(&self, _weights: &RecommenderConfig) -> f32 {
+        1.0
+    }
+
+    // the vibes that fit the moment, with shares from 0 to 1
+    async fn get_vibes(&self, moment: &Moment) -> Result<Vec<(String, f32)>, VibingError>;
+
+    // the neighbours of those vibes, tried at half weight when no track matches anything
+    async fn get_adjacent_vibes(&self, _moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+        Ok(Vec::new())
+    }
+
+    // every vibe name the source can put forward, checked against the vibes table at startup
+    fn get_vibe_names(&self) -> Vec<String>;
+}
+
+// the time periods of the moment, both of them around a boundary
+pub struct TimeSource {
+    time_data: TimeData,
+}
+
+impl TimeSource {
+    pub fn new(time_data: TimeData) -> Self {
+        Self { time_data }
+    }
+}
+
+#[async_trait]
+impl ContextSource for TimeSource {
+    fn get_name(&self) -> &str {
+        "time"
+    }
+
+    fn get_default_weight(&self, weights: &RecommenderConfig) -> f32 {
+        weights.time_weight
+    }
+
+    async fn get_vibes(&self, moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+        Ok(self.time_data.get_time_blend_at(moment.now).iter()
+            .map(|(time, share)| (time.vibe_name().to_string(), *share))
+            .collect())
+    }
+
+    async fn get_adjacent_vibes(&self, moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+        let time = self.time_data.get_time_at(moment.now);
+        Ok(time.adjacent_vibe_names().map(|name| (name.to_string(), 1.0)).to_vec())
+    }
+
+    fn get_vibe_names(&self) -> Vec<String> {
+        TimePeriod::all(0.0).iter().map(|time| time.vibe_name().to_string()).collect()
+    }
+}
+
+pub struct SeasonSource {
+    time_data: TimeData,
+}
+
+impl SeasonSource {
+    pub fn new(time_data: TimeData) -> Self {
+        Self { time_data }
+    }
+}
+
+#[async_trait]
+impl ContextSource for SeasonSource {
+    fn get_name(&self) -> &str {
+        "season"
+    }
+
+    fn get_default_weight(&self, weights: &RecommenderConfig) -> f32 {
+        weights.season_weight
+    }
+
+    async fn get_vibes(&self, moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+        Ok(vec![(self.time_data.get_season_at(moment.now).vibe_name().to_string(), 1.0)])
+    }
+
+    async fn get_adjacent_vibes(&self, moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+        let season = self.time_data.get_season_at(moment.now);
+        Ok(season.adjacent_vibe_names().map(|name| (name.to_string(), 1.0)).to_vec())
+    }
+
+    fn get_vibe_names(&self) -> Vec<String> {
+        Season::all(1).iter().map(|season| season.vibe_name().to_string()).collect()
+    }
+}
+
+// every vibe of a weather counts fully, a rainy and cold track fits a rainy cold day best
+pub struct WeatherSource {
+    provider: Arc<dyn WeatherProvider>,
+}
+
+impl WeatherSource {
+    pub fn new(provider: Arc<dyn WeatherProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl ContextSource for WeatherSource {
+    fn get_name(&self) -> &str {
+        "weather"
+    }
+
+    fn get_default_weight(&self, weights: &RecommenderConfig) -> f32 {
+        weights.weather_weight
+    }
+
+    async fn get_vibes(&self, moment: &Moment) -> Result<Vec<(String, f32)>, VibingError> {
+        let current = match moment.weather {
+            Some(weather) => weather,
+            None => self.provider.get_weather().await?,
+        };
+        let weathers = match moment.upcoming {
+            Some((upcoming, share)) => vec![(current, 1.0 - share), (upcoming, share)],
+            None => vec![(current, 1.0)],
+        };
+
+        Ok(weathers.iter()
+            .flat_map(|(weather, share)| weather.iter().map(move |weather| (weather, *share)))
+            .filter(|(weather, _)| !matches!(weather, Weather::Default(_)))
+            .map(|(weather, share)| (weather.vibe_name().to_string(), share))
+            .collect())
+    }
+
+    fn get_vibe_names(&self) -> Vec<String> {
+        Weather::all(0.0).iter()
+            .filter(|weather| !matches!(weather, Weather::Default(_)))
+            .map(|weather| weather.vibe_name().to_string())
+            .collect()
+    }
+}
@@ -1,11 +1,18 @@
-use chrono::{self, Timelike, Datelike};
+use std::{fmt, mem::discriminant, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{self, DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike, Utc};
 use reqwest;
 use serde::Deserialize;
 
+use crate::{configuration::{HttpConfig, TimePeriodConfig, WeatherConfig, WeatherThresholds}, error::VibingError, events::{EventBus, VibingEvent}, solar, weather_mapping::WeatherMapping, weather_providers::WeatherProvider};
+
 pub type Hour = f32;
 pub type Month = u8;
 pub type Temperature = f32;
 
+type PeriodAt = fn(Hour) -> TimePeriod;
+
 #[derive(Debug, Clone, Copy)]
 pub enum TimePeriod {
     Dawn(Hour),
@@ -31,12 +38,241 @@ pub enum Weather {
     Rainy(Temperature),
     Windy(Temperature),
     Cloudy(Temperature),
+    Foggy(Temperature),
+    Snowy(Temperature),
     Stormy(Temperature),
     Hotty(Temperature),
     Coldy(Temperature),
     Default(Temperature),
 }
 
+impl TimePeriod {
+    // name of the matching row in the vibes table
+    pub fn vibe_name(&self) -> &'static str {
+        match self {
+            TimePeriod::Dawn(_) => "dawn",
+            TimePeriod::Morning(_) => "morning",
+            TimePeriod::Noon(_) => "noon",
+            TimePeriod::Afternoon(_) => "afternoon",
+            TimePeriod::Evening(_) => "evening",
+            TimePeriod::Dusk(_) => "dusk",
+            TimePeriod::Night(_) => "night",
+        }
+    }
+
+    // the periods right before and after, night wraps around to dawn
+    pub fn adjacent_vibe_names(&self) -> [&'static str; 2] {
+        match self {
+            TimePeriod::Dawn(_) => ["night", "morning"],
+            TimePeriod::Morning(_) => ["dawn", "noon"],
+            TimePeriod::Noon(_) => ["morning", "afternoon"],
+            TimePeriod::Afternoon(_) => ["noon", "dusk"],
+            TimePeriod::Dusk(_) => ["afternoon", "evening"],
+            TimePeriod::Evening(_) => ["dusk", "night"],
+            TimePeriod::Night(_) => ["evening", "dawn"],
+        }
+    }
+
+    pub fn all(hour: Hour) -> [TimePeriod; 7] {
+        [
+            TimePeriod::Dawn(hour),
+            TimePeriod::Morning(hour),
+            TimePeriod::Noon(hour),
+            TimePeriod::Afternoon(hour),
+            TimePeriod::Dusk(hour),
+            TimePeriod::Evening(hour),
+            TimePeriod::Night(hour),
+        ]
+    }
+}
+
+impl Season {
+    pub fn vibe_name(&self) -> &'static str {
+        match self {
+            Season::Spring(_) => "spring",
+            Season::Summer(_) => "summer",
+            Season::Autumn(_) => "autumn",
+            Season::Winter(_) => "winter",
+        }
+    }
+
+    pub fn adjacent_vibe_names(&self) -> [&'static str; 2] {
+        match self {
+            Season::Spring(_) => ["winter", "summer"],
+            Season::Summer(_) => ["spring", "autumn"],
+            Season::Autumn(_) => ["summer", "winter"],
+            Season::Winter(_) => ["autumn", "spring"],
+        }
+    }
+
+    pub fn all(month: Month) -> [Season; 4] {
+        [Season::Spring(month), Season::Summer(month), Season::Autumn(month), Season::Winter(month)]
+    }
+}
+
+impl Weather {
+    pub fn vibe_name(&self) -> &'static str {
+        match self {
+            Weather::Sunny(_) => "sunny",
+            Weather::Cloudy(_) => "cloudy",
+            Weather::Foggy(_) => "foggy",
+            Weather::Rainy(_) => "rainy",
+            Weather::Snowy(_) => "snowy",
+            Weather::Stormy(_) => "stormy",
+            Weather::Windy(_) => "windy",
+            Weather::Coldy(_) => "coldy",
+            Weather::Hotty(_) => "hotty",
+            Weather::Default(_) => "none",
+        }
+    }
+
+    pub fn from_vibe(name: &str, temp: Temperature) -> Option<Self> {
+        match name {
+            "sunny" => Some(Weather::Sunny(temp)),
+            "rainy" => Some(Weather::Rainy(temp)),
+            "windy" => Some(Weather::Windy(temp)),
+            "cloudy" => Some(Weather::Cloudy(temp)),
+            "foggy" => Some(Weather::Foggy(temp)),
+            "snowy" => Some(Weather::Snowy(temp)),
+            "stormy" => Some(Weather::Stormy(temp)),
+            "hotty" => Some(Weather::Hotty(temp)),
+            "coldy" => Some(Weather::Coldy(temp)),
+            "none" => Some(Weather::Default(temp)),
+            _ => None,
+        }
+    }
+
+    pub fn all(temp: Temperature) -> [Weather; 10] {
+        [
+            Weather::Sunny(temp),
+            Weather::Cloudy(temp),
+            Weather::Foggy(temp),
+            Weather::Rainy(temp),
+            Weather::Snowy(temp),
+            Weather::Stormy(temp),
+            Weather::Windy(temp),
+            Weather::Hotty(temp),
+            Weather::Coldy(temp),
+            Weather::Default(temp),
+        ]
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Weather::Sunny(_) => 0,
+            Weather::Cloudy(_) => 1,
+            Weather::Foggy(_) => 2,
+            Weather::Rainy(_) => 3,
+            Weather::Snowy(_) => 4,
+            Weather::Stormy(_) => 5,
+            Weather::Windy(_) => 6,
+            Weather::Hotty(_) => 7,
+            Weather::Coldy(_) => 8,
+            Weather::Default(_) => 9,
+        }
+    }
+
+    fn temperature(&self) -> Temperature {
+        match *self {
+            Weather::Sunny(temp) | Weather::Cloudy(temp) | Weather::Foggy(temp) | Weather::Rainy(temp) | Weather::Snowy(temp)
+            | Weather::Stormy(temp) | Weather::Windy(temp) | Weather::Hotty(temp) | Weather::Coldy(temp) | Weather::Default(temp) => temp,
+        }
+    }
+}
+
+// every weather vibe that holds at once, a day can be rainy and cold. they share one temperature,
+// and an empty set is the Default weather
+#[derive(Clone, Copy, PartialEq)]
+pub struct WeatherSet {
+    vibes: u16,
+    temperature: Temperature,
+}
+
+impl WeatherSet {
+    pub fn new(temperature: Temperature) -> Self {
+        Self { vibes: 0, temperature }
+    }
+
+    // the sky as the provider describes it, plus hotty, coldy and windy from the measurements
+    pub fn classify(sky: Weather, humidity: Option<f32>, wind_kmph: Option<f32>, thresholds: &WeatherThresholds) -> Self {
+        let temp = sky.temperature();
+        let humid = humidity.is_some_and(|humidity| humidity >= thresholds.humid_percent);
+        let mut weather = Self::from(sky);
+
+        if temp >= thresholds.hot_celsius || (humid && temp >= thresholds.humid_hot_celsius) {
+            weather = weather.with(Weather::Hotty(temp));
+        }
+        if temp <= thresholds.cold_celsius {
+            weather = weather.with(Weather::Coldy(temp));
+        }
+        if wind_kmph.is_some_and(|wind| wind >= thresholds.windy_kmph) {
+            weather = weather.with(Weather::Windy(temp));
+        }
+
+        weather
+    }
+
+    pub fn with(mut self, weather: Weather) -> Self {
+        if !matches!(weather, Weather::Default(_)) {
+            self.vibes |= 1 << weather.index();
+        }
+        self
+    }
+
+    pub fn contains(&self, weather: Weather) -> bool {
+        match weather {
+            Weather::Default(_) => self.vibes == 0,
+            weather => self.vibes & (1 << weather.index()) != 0,
+        }
+    }
+
+    pub fn get_temperature(&self) -> Temperature {
+        self.temperature
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Weather> + use<> {
+        let weather = *self;
+        Weather::all(self.temperature).into_iter().filter(move |vibe| weather.contains(*vibe))
+    }
+
+    // the sky comes first, then windy, hotty and coldy
+    pub fn primary(&self) -> Weather {
+        self.iter().next().unwrap_or(Weather::Default(self.temperature))
+    }
+
+    pub fn vibe_names(&self) -> Vec<&'static str> {
+        self.iter().map(|weather| weather.vibe_name()).collect()
+    }
+
+    // how the set is stored with plays and feedback, like "rainy,coldy"
+    pub fn vibe_name(&self) -> String {
+        self.vibe_names().join(",")
+    }
+
+    pub fn from_vibes(names: &str, temp: Temperature) -> Option<Self> {
+        names.split(',')
+            .map(|name| Weather::from_vibe(name.trim(), temp))
+            .try_fold(Self::new(temp), |weather, vibe| Some(weather.with(vibe?)))
+    }
+
+    // same vibes whatever the temperature
+    pub fn same_vibes(&self, other: &WeatherSet) -> bool {
+        self.vibes == other.vibes
+    }
+}
+
+impl fmt::Debug for WeatherSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl From<Weather> for WeatherSet {
+    fn from(weather: Weather) -> Self {
+        Self::new(weather.temperature()).with(weather)
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct WeatherDesc {
     value: String,
@@ -47,128 +283,612 @@ struct WeatherDesc {
 #[allow(non_camel_case_types, non_snake_case)]
 struct current_condition {
     temp_C: String,
+    humidity: Option<String>,
+    windspeedKmph: Option<String>,
+    weatherCode: Option<String>,
+    weatherDesc: Vec<WeatherDesc>,
+}
+
+// one step of the j1 forecast, every three hours
+#[derive(Deserialize, Debug)]
+#[allow(non_snake_case)]
+struct Hourly {
+    // hhmm without leading zeros, "0", "300" ... "2100"
+    time: String,
+    tempC: String,
+    humidity: Option<String>,
+    windspeedKmph: Option<String>,
+    weatherCode: Option<String>,
     weatherDesc: Vec<WeatherDesc>,
 }
 
+#[derive(Deserialize, Debug)]
+struct ForecastDay {
+    date: NaiveDate,
+    hourly: Vec<Hourly>,
+}
+
 #[derive(Deserialize, Debug)]
 struct WeatherResponse {
     current_condition: Vec<current_condition>,
+    #[serde(default)]
+    weather: Vec<ForecastDay>,
+}
+
+// the weather expected from start until the next forecast
+#[derive(Debug, Clone, Copy)]
+pub struct ForecastHour {
+    pub start: DateTime<Local>,
+    pub weather: WeatherSet,
 }
 
-pub struct TimeData;
+// the forecast covering the time, None before the first one
+pub fn forecast_at(forecast: &[ForecastHour], at: DateTime<Local>) -> Option<&ForecastHour> {
+    forecast.iter().take_while(|hour| hour.start <= at).last()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Context {
+    pub time: TimePeriod,
+    pub season: Season,
+    pub weather: WeatherSet,
+}
+
+impl Context {
+    // the payloads (hour, month, temperature) change all the time, only the categories matter
+    pub fn same_vibe(&self, other: &Context) -> bool {
+        discriminant(&self.time) == discriminant(&other.time)
+            && discriminant(&self.season) == discriminant(&other.season)
+            && self.weather.same_vibes(&other.weather)
+    }
+
+    // every vibe the context stands for, as feedback is looked up by
+    pub fn vibe_names(&self) -> Vec<String> {
+        [self.time.vibe_name(), self.season.vibe_name()].into_iter()
+            .chain(self.weather.vibe_names())
+            .map(String::from)
+            .collect()
+    }
+
+    // the context named by its vibes, e.g. ("night", "winter", "rainy,coldy"). the payloads are left at 0
+    pub fn from_vibes(time: &str, season: &str, weather: &str) -> Option<Self> {
+        Some(Self {
+            time: TimePeriod::all(0.0).into_iter().find(|period| period.vibe_name() == time)?,
+            season: Season::all(0).into_iter().find(|candidate| candidate.vibe_name() == season)?,
+            weather: WeatherSet::from_vibes(weather, 0.0)?,
+        })
+    }
+}
+
+// remembers the last context and tells the subscribers when its categories change
+pub struct ContextTracker {
+    context: Option<Context>,
+    events: EventBus,
+}
+
+impl ContextTracker {
+    pub fn new(events: EventBus) -> Self {
+        Self { context: None, events }
+    }
+
+    pub fn get_context(&self) -> Option<Context> {
+        self.context
+    }
+
+    // returns true if the context changed
+    pub fn update(&mut self, context: Context) -> bool {
+        let changed = !self.context.is_some_and(|current| current.same_vibe(&context));
+        self.context = Some(context);
+
+        if changed {
+            self.events.publish(VibingEvent::ContextChanged {
+                time: context.time,
+                season: context.season,
+                weather: context.weather,
+            });
+        }
+
+        changed
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TimeData {
+    periods: TimePeriodConfig,
+}
 
 impl TimeData {
-    pub fn get_time() -> TimePeriod {
-        let now = chrono::Local::now();
+    pub fn new(periods: TimePeriodConfig) -> Self {
+        Self { periods }
+    }
+
+    pub fn get_time(&self) -> TimePeriod {
+        self.get_time_at(chrono::Local::now())
+    }
+
+    // start hours on the day of `now`. with a location dawn, noon, dusk and night follow the sun:
+    // dawn at civil dawn, morning an hour after sunrise, noon an hour either side of solar noon,
+    // dusk an hour before sunset, evening at civil dusk and night once astronomical twilight ends.
+    // a twilight the sun never reaches falls back on the one before it, the fixed hours are only
+    // kept when there is no sunrise or sunset at all (polar day or night).
+    // the hours are on the local clock and may wrap past midnight, when the machine's timezone
+    // is far from the location's the whole solar day can sit across two local dates
+    pub fn get_periods_at(&self, now: DateTime<Local>) -> TimePeriodConfig {
+        self.solar_periods_at(now)
+            .filter(in_circular_order)
+            .unwrap_or_else(|| self.periods.clone())
+    }
+
+    fn solar_periods_at(&self, now: DateTime<Local>) -> Option<TimePeriodConfig> {
+        let sun = solar::sun_times(now.date_naive(), self.periods.latitude?, self.periods.longitude?);
+        let (sunrise, sunset) = (sun.sunrise?, sun.sunset?);
+        // whatever local date the time falls on
+        let hour_of = |time: DateTime<Utc>, shift: f32| {
+            let time = time.with_timezone(&Local);
+            (time.hour() as f32 + time.minute() as f32 / 60.0 + time.second() as f32 / 3600.0 + shift).rem_euclid(24.0)
+        };
+
+        Some(TimePeriodConfig {
+            dawn: sun.civil_dawn.map_or(hour_of(sunrise, -0.5), |dawn| hour_of(dawn, 0.0)),
+            morning: hour_of(sunrise, 1.0),
+            noon: hour_of(sun.noon, -1.0),
+            afternoon: hour_of(sun.noon, 1.0),
+            dusk: hour_of(sunset, -1.0),
+            evening: sun.civil_dusk.map_or(hour_of(sunset, 0.5), |dusk| hour_of(dusk, 0.0)),
+            night: match (sun.astronomical_dusk, sun.civil_dusk) {
+                (Some(dusk), _) => hour_of(dusk, 0.0),
+                // summer at mid latitudes, the sky never gets fully dark
+                (None, Some(dusk)) => hour_of(dusk, 1.0),
+                (None, None) => hour_of(sunset, 1.5),
+            },
+            ..self.periods.clone()
+        })
+    }
+
+    pub fn get_time_at(&self, now: DateTime<Local>) -> TimePeriod {
+        let hour = now.hour() as f32 + now.minute() as f32 / 60.0;
+        let p = &self.get_periods_at(now);
+        let starts: [(Hour, PeriodAt); 7] = [
+            (p.dawn, TimePeriod::Dawn),
+            (p.morning, TimePeriod::Morning),
+            (p.noon, TimePeriod::Noon),
+            (p.afternoon, TimePeriod::Afternoon),
+            (p.dusk, TimePeriod::Dusk),
+            (p.evening, TimePeriod::Evening),
+            (p.night, TimePeriod::Night),
+        ];
+
+        // the period that started last, going back around midnight if need be
+        let (_, period) = starts.iter()
+            .min_by(|a, b| (hour - a.0).rem_euclid(24.0).total_cmp(&(hour - b.0).rem_euclid(24.0)))
+            .unwrap();
+        period(hour)
+    }
+
+    pub fn get_time_blend(&self) -> Vec<(TimePeriod, f32)> {
+        self.get_time_blend_at(chrono::Local::now())
+    }
+
+    // the periods the time belongs to with their membership, heaviest first, summing to 1.
+    // within blend_minutes around a start hour both periods share it, linearly
+    pub fn get_time_blend_at(&self, now: DateTime<Local>) -> Vec<(TimePeriod, f32)> {
         let hour = now.hour() as f32 + now.minute() as f32 / 60.0;
+        let minute_of_day = (now.hour() * 60 + now.minute()) as f32;
+        let p = &self.get_periods_at(now);
+        let blend = p.blend_minutes;
+        let starts: [(Hour, PeriodAt); 7] = [
+            (p.dawn, TimePeriod::Dawn),
+            (p.morning, TimePeriod::Morning),
+            (p.noon, TimePeriod::Noon),
+            (p.afternoon, TimePeriod::Afternoon),
+            (p.dusk, TimePeriod::Dusk),
+            (p.evening, TimePeriod::Evening),
+            (p.night, TimePeriod::Night),
+        ];
+
+        for (i, (start, period)) in starts.iter().enumerate() {
+            // signed minutes since the start, wrapping around midnight
+            let offset = (minute_of_day - start * 60.0 + 36.0 * 60.0) % (24.0 * 60.0) - 12.0 * 60.0;
+            if offset.abs() < blend / 2.0 {
+                let share = (offset + blend / 2.0) / blend;
+                let previous = starts[(i + starts.len() - 1) % starts.len()].1;
+                let mut periods = vec![(period(hour), share), (previous(hour), 1.0 - share)];
+                periods.sort_by(|a, b| b.1.total_cmp(&a.1));
+                return periods;
+            }
+        }
 
-        match hour {
-            h if (5.0..=7.0).contains(&h) => TimePeriod::Dawn(h),
-            h if (7.0..=11.0).contains(&h) => TimePeriod::Morning(h),
-            h if (11.0..=13.0).contains(&h) => TimePeriod::Noon(h),
-            h if (13.0..=17.0).contains(&h) => TimePeriod::Afternoon(h),
-            h if (17.0..=19.0).contains(&h) => TimePeriod::Dusk(h),
-            h if (19.0..=22.0).contains(&h) => TimePeriod::Evening(h),
-            _ => TimePeriod::Night(hour),
+        vec![(self.get_time_at(now), 1.0)]
+    }
+
+    // every period start and midnight in [from, to), where the context can change
+    pub fn get_boundaries_between(&self, from: DateTime<Local>, to: DateTime<Local>) -> Vec<DateTime<Local>> {
+        let mut boundaries = Vec::new();
+        for date in from.date_naive().iter_days().take_while(|date| *date <= to.date_naive()) {
+            let Some(midnight) = Local.from_local_datetime(&date.and_time(chrono::NaiveTime::MIN)).earliest() else {
+                continue;
+            };
+            let p = self.get_periods_at(midnight + chrono::Duration::hours(12));
+            for hour in [0.0, p.dawn, p.morning, p.noon, p.afternoon, p.dusk, p.evening, p.night] {
+                // rounded up so the boundary already falls in the new period
+                boundaries.push(midnight + chrono::Duration::minutes((hour * 60.0).ceil() as i64));
+            }
         }
+        boundaries.retain(|boundary| (from..to).contains(boundary));
+        boundaries.sort();
+
+        boundaries
     }
 
-    pub fn get_season() -> Season {
-        let now = chrono::Local::now();
+    pub fn get_season(&self) -> Season {
+        self.get_season_at(chrono::Local::now())
+    }
+
+    pub fn get_season_at(&self, now: DateTime<Local>) -> Season {
         let month = now.month() as u8;
         let _day = now.day() as u8;
 
         match month {
-            3 | 4 | 5 => Season::Spring(month),
-            6 | 7 | 8 => Season::Summer(month),
-            9 | 10 | 11 => Season::Autumn(month),
+            3..=5 => Season::Spring(month),
+            6..=8 => Season::Summer(month),
+            9..=11 => Season::Autumn(month),
             _ => Season::Winter(month), // 12, 1, 2
         }
     }
 }
 
-pub struct WeatherData;
+#[derive(Debug, Clone)]
+pub struct WeatherData {
+    config: WeatherConfig,
+    mapping: WeatherMapping,
+    client: ApiClient,
+}
+
+impl Default for WeatherData {
+    fn default() -> Self {
+        Self::new(WeatherConfig::default())
+    }
+}
 
 impl WeatherData {
-    pub async fn get_weather() -> Weather {
-        // let weather_host = std::env::var("WEATHER_HOST")
-        //     .expect("WEATHER_HOST is not existed in .env");
-        let weather_host = "https://wttr.in/";
-        let url = format!(
-            "{}/{}?format=j1", weather_host, "Hanoi"
-        );
-        let response = get_api(&url)
-            .await
-            .expect("cannot get weather data");
-
-        let weather_data: WeatherResponse = serde_json::from_str(&response).expect("cannot parse weather data");
-
-        let current_condition = &weather_data.current_condition[0];
-        let temp: f32 = current_condition.temp_C.parse().expect("cannot parse temperature");
-        let weather_desc = &current_condition.weatherDesc[0].value;
-
-        match weather_desc.as_str() {
-            "Sunny" => Weather::Sunny(temp),
-            "Partly cloudy" => Weather::Cloudy(temp),
-            "Cloudy" => Weather::Cloudy(temp),
-            "Overcast" => Weather::Cloudy(temp),
-            "Mist" => Weather::Cloudy(temp),
-            "Patchy rain possible" => Weather::Rainy(temp),
-            "Patchy snow possible" => Weather::Rainy(temp),
-            "Patchy sleet possible" => Weather::Rainy(temp),
-            "Patchy freezing drizzle possible" => Weather::Rainy(temp),
-            "Thundery outbreaks possible" => Weather::Stormy(temp),
-            "Blowing snow" => Weather::Windy(temp),
-            "Blizzard" => Weather::Stormy(temp),
-            "Fog" => Weather::Cloudy(temp),
-            "Freezing fog" => Weather::Cloudy(temp),
-            "Patchy light drizzle" => Weather::Rainy(temp),
-            "Light drizzle" => Weather::Rainy(temp),
-            "Freezing drizzle" => Weather::Rainy(temp),
-            "Heavy freezing drizzle" => Weather::Rainy(temp),
-            "Patchy light rain" => Weather::Rainy(temp),
-            "Light rain" => Weather::Rainy(temp),
-            "Moderate rain at times" => Weather::Rainy(temp),
-            "Moderate rain" => Weather::Rainy(temp),
-            "Heavy rain at times" => Weather::Rainy(temp),
-            "Heavy rain" => Weather::Rainy(temp),
-            "Light freezing rain" => Weather::Rainy(temp),
-            "Moderate or heavy freezing rain" => Weather::Rainy(temp),
-            "Light sleet" => Weather::Rainy(temp),
-            "Moderate or heavy sleet" => Weather::Rainy(temp),
-            "Patchy light snow" => Weather::Rainy(temp),
-            "Light snow" => Weather::Rainy(temp),
-            "Patchy moderate snow" => Weather::Rainy(temp),
-            "Moderate snow" => Weather::Rainy(temp),
-            "Patchy heavy snow" => Weather::Rainy(temp),
-            "Heavy snow" => Weather::Rainy(temp),
-            "Ice pellets" => Weather::Rainy(temp),
-            "Light rain shower" => Weather::Rainy(temp),
-            "Moderate or heavy rain shower" => Weather::Rainy(temp),
-            "Torrential rain shower" => Weather::Rainy(temp),
-            "Light sleet showers" => Weather::Rainy(temp),
-            "Moderate or heavy sleet showers" => Weather::Rainy(temp),
-            "Light snow showers" => Weather::Rainy(temp),
-            "Moderate or heavy snow showers" => Weather::Rainy(temp),
-            "Light showers of ice pellets" => Weather::Rainy(temp),
-            "Moderate or heavy showers of ice pellets" => Weather::Rainy(temp),
-            "Patchy light rain with thunder" => Weather::Stormy(temp),
-            "Moderate or heavy rain with thunder" => Weather::Stormy(temp),
-            "Patchy light snow with thunder" => Weather::Stormy(temp),
-            "Moderate or heavy snow with thunder" => Weather::Stormy(temp),
-            "Patchy rain nearby" => Weather::Rainy(temp),
-            "Light rain, thunderstorm in vicinity, rain with thunderstorm" => Weather::Rainy(temp),
-            _ => Weather::Default(temp),
-        }
-    }
-
-}
-
-pub async fn get_api(url: &str) -> Result<String, reqwest::Error> {
-    let response = reqwest::get(url)
-        .await?
-        .text()
-        .await?;
-    
-    Ok(response)
+    pub fn new(config: WeatherConfig) -> Self {
+        let mapping = WeatherMapping::wttr().with_overrides(&config.mapping);
+        let client = ApiClient::new(config.http.clone());
+        Self { config, mapping, client }
+    }
+
+    // parse a wttr.in j1 payload
+    pub fn parse_weather(response: &str, mapping: &WeatherMapping, thresholds: &WeatherThresholds) -> Result<WeatherSet, VibingError> {
+        let weather_data: WeatherResponse = serde_json::from_str(response)?;
+
+        let current_condition = weather_data.current_condition.first()
+            .ok_or_else(|| VibingError::Parse("no current_condition in weather data".to_string()))?;
+        let temp: f32 = current_condition.temp_C.parse()?;
+        let weather_desc = current_condition.weatherDesc.first()
+            .map(|desc| desc.value.as_str())
+            .unwrap_or_default();
+
+        Ok(Self::classify(
+            temp, &current_condition.weatherCode, weather_desc,
+            &current_condition.humidity, &current_condition.windspeedKmph, mapping, thresholds
+        ))
+    }
+
+    // the hourly forecasts of a wttr.in j1 payload, oldest first. the times are taken as local
+    pub fn parse_forecast(response: &str, mapping: &WeatherMapping, thresholds: &WeatherThresholds) -> Result<Vec<ForecastHour>, VibingError> {
+        let weather_data: WeatherResponse = serde_json::from_str(response)?;
+
+        let mut forecast = Vec::new();
+        for day in weather_data.weather {
+            for hour in day.hourly {
+                let hhmm: u32 = hour.time.parse()
+                    .map_err(|_| VibingError::Parse(format!("not a forecast time: {:?}", hour.time)))?;
+                let Some(start) = day.date.and_hms_opt(hhmm / 100, hhmm % 100, 0)
+                    .and_then(|start| Local.from_local_datetime(&start).earliest())
+                else {
+                    continue;
+                };
+                let temp: f32 = hour.tempC.parse()?;
+                let weather_desc = hour.weatherDesc.first().map(|desc| desc.value.as_str()).unwrap_or_default();
+
+                forecast.push(ForecastHour {
+                    start,
+                    weather: Self::classify(temp, &hour.weatherCode, weather_desc, &hour.humidity, &hour.windspeedKmph, mapping, thresholds),
+                });
+            }
+        }
+        forecast.sort_by_key(|hour| hour.start);
+
+        Ok(forecast)
+    }
+
+    // wttr.in sends every number as a string
+    fn classify(
+        temp: Temperature, code: &Option<String>, description: &str, humidity: &Option<String>, wind_kmph: &Option<String>,
+        mapping: &WeatherMapping, thresholds: &WeatherThresholds,
+    ) -> WeatherSet {
+        let number = |value: &Option<String>| value.as_deref().and_then(|value| value.parse::<f32>().ok());
+        let code = code.as_deref().and_then(|code| code.parse().ok());
+        let sky = mapping.get_weather(code, description, temp);
+
+        WeatherSet::classify(sky, number(humidity), number(wind_kmph), thresholds)
+    }
+
+    fn get_url(&self) -> String {
+        format!("{}/{}?format=j1", self.config.host.trim_end_matches('/'), self.config.city)
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for WeatherData {
+    async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+        let response = self.client.get(&self.get_url()).await?;
+
+        Self::parse_weather(&response, &self.mapping, &self.config.thresholds)
+    }
+
+    async fn get_forecast(&self) -> Result<Vec<ForecastHour>, VibingError> {
+        Ok(self.get_weather_and_forecast().await?.1)
+    }
+
+    // the j1 payload has both, one request is enough
+    async fn get_weather_and_forecast(&self) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+        let response = self.client.get(&self.get_url()).await?;
+
+        Ok((
+            Self::parse_weather(&response, &self.mapping, &self.config.thresholds)?,
+            Self::parse_forecast(&response, &self.mapping, &self.config.thresholds)?,
+        ))
+    }
+}
+
+// one client per provider, so its connections are reused between requests
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    client: reqwest::Client,
+    http: HttpConfig,
+}
+
+impl ApiClient {
+    pub fn new(http: HttpConfig) -> Self {
+        // only fails when the tls backend can't start, where reqwest::Client::new panics too
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(http.timeout_secs))
+            .build()
+            .unwrap();
+        Self { client, http }
+    }
+
+    // gives up after http.retries more attempts, waiting http.backoff_ms before the first retry and
+    // twice as long before each next one. only timeouts, failed connections, 5xx and 429 are retried,
+    // any other error status is returned right away
+    pub async fn get(&self, url: &str) -> Result<String, VibingError> {
+        let mut attempt = 0;
+        loop {
+            let response = async {
+                self.client.get(url).send().await?.error_for_status()?.text().await
+            }.await;
+
+            match response {
+                Ok(response) => return Ok(response),
+                Err(e) if attempt < self.http.retries && Self::is_transient(&e) => {
+                    let backoff = self.http.backoff_ms.saturating_mul(1 << attempt.min(16));
+                    eprintln!("request to {url} failed, retrying in {backoff}ms: {e}");
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn is_transient(e: &reqwest::Error) -> bool {
+        e.is_timeout() || e.is_connect() || e.status().is_some_and(|status| {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        })
+    }
+}
+
+// each start after the one before it once around the clock, with room for the blends
+fn in_circular_order(p: &TimePeriodConfig) -> bool {
+    let starts = [p.dawn, p.morning, p.noon, p.afternoon, p.dusk, p.evening, p.night];
+    let since_dawn: Vec<_> = starts.iter().map(|start| (start - p.dawn).rem_euclid(24.0)).collect();
+    let gaps: Vec<_> = since_dawn.windows(2).map(|pair| pair[1] - pair[0]).chain([24.0 - since_dawn[6]]).collect();
+
+    gaps.iter().all(|gap| *gap > 0.0 && gap * 60.0 >= p.blend_minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+
+    use super::*;
+
+    // answers one request per status, in order, always with the same body
+    async fn serve(statuses: &'static [&'static str], body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                assert!(socket.read(&mut request).await.unwrap() > 0);
+                let response = format!("HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}", body.len());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn retries_failed_requests() {
+        let client = ApiClient::new(HttpConfig { timeout_secs: 5, retries: 2, backoff_ms: 1 });
+
+        let url = serve(&["503 Service Unavailable", "429 Too Many Requests", "200 OK"], "ok").await;
+        assert_eq!(client.get(&url).await.unwrap(), "ok");
+
+        let url = serve(&["503 Service Unavailable", "503 Service Unavailable", "503 Service Unavailable"], "ok").await;
+        assert!(client.get(&url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let client = ApiClient::new(HttpConfig { timeout_secs: 5, retries: 2, backoff_ms: 1 });
+
+        // a retry would get the 200
+        let url = serve(&["404 Not Found", "200 OK"], "ok").await;
+        match client.get(&url).await {
+            Err(VibingError::Http(e)) => assert_eq!(e.status(), Some(reqwest::StatusCode::NOT_FOUND)),
+            other => panic!("expected the 404, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_weather_and_forecast_from_one_response() {
+        let j1 = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecast/wttr_rain_at_noon.json"));
+        let host = serve(&["200 OK"], j1).await;
+        let http = HttpConfig { timeout_secs: 5, retries: 0, backoff_ms: 1 };
+        let config = WeatherConfig { host, city: "Lyon".to_string(), http, ..WeatherConfig::default() };
+
+        // nobody answers a second request
+        let (weather, forecast) = WeatherData::new(config).get_weather_and_forecast().await.unwrap();
+        assert_eq!(weather.vibe_name(), "cloudy");
+        assert!(forecast.iter().any(|hour| hour.weather.vibe_name() == "rainy"));
+    }
+
+    fn blend_at(time_data: &TimeData, hour: u32, minute: u32) -> Vec<(&'static str, f32)> {
+        let now = Local.with_ymd_and_hms(2025, 6, 1, hour, minute, 0).unwrap();
+        time_data.get_time_blend_at(now).iter().map(|(period, share)| (period.vibe_name(), *share)).collect()
+    }
+
+    #[test]
+    fn blends_periods_around_their_start() {
+        let time_data = TimeData::new(TimePeriodConfig { blend_minutes: 20.0, ..TimePeriodConfig::default() });
+
+        assert_eq!(blend_at(&time_data, 6, 30), [("dawn", 1.0)]);
+        assert_eq!(blend_at(&time_data, 6, 55), [("dawn", 0.75), ("morning", 0.25)]);
+        assert_eq!(blend_at(&time_data, 7, 5), [("morning", 0.75), ("dawn", 0.25)]);
+        assert_eq!(blend_at(&time_data, 7, 10), [("morning", 1.0)]);
+        assert_eq!(blend_at(&time_data, 19, 0), [("evening", 0.5), ("dusk", 0.5)]);
+
+        // night wraps around to dawn
+        assert_eq!(blend_at(&time_data, 4, 55), [("night", 0.75), ("dawn", 0.25)]);
+        assert_eq!(blend_at(&time_data, 0, 0), [("night", 1.0)]);
+    }
+
+    #[test]
+    fn hard_boundaries_without_a_blend() {
+        let time_data = TimeData::new(TimePeriodConfig { blend_minutes: 0.0, ..TimePeriodConfig::default() });
+
+        assert_eq!(blend_at(&time_data, 6, 59), [("dawn", 1.0)]);
+        assert_eq!(blend_at(&time_data, 7, 0), [("morning", 1.0)]);
+        assert!(matches!(time_data.get_time_at(Local.with_ymd_and_hms(2025, 6, 1, 18, 30, 0).unwrap()), TimePeriod::Dusk(_)));
+        assert!(matches!(time_data.get_time_at(Local.with_ymd_and_hms(2025, 6, 1, 21, 0, 0).unwrap()), TimePeriod::Evening(_)));
+    }
+
+    #[test]
+    fn follows_the_sun_with_a_location() {
+        // a longitude matching the local offset keeps the whole solar day on the local date
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+        let offset = Local.with_ymd_and_hms(2025, 3, 20, 12, 0, 0).unwrap().offset().local_minus_utc() as f64 / 3600.0;
+        let (latitude, longitude) = (45.0, offset * 15.0);
+        let time_data = TimeData::new(TimePeriodConfig { latitude: Some(latitude), longitude: Some(longitude), ..TimePeriodConfig::default() });
+
+        let sun = solar::sun_times(date, latitude, longitude);
+        let time_at = |time: DateTime<Utc>, minutes: i64| time_data.get_time_at((time + chrono::Duration::minutes(minutes)).with_timezone(&Local)).vibe_name();
+
+        assert_eq!(time_at(sun.civil_dawn.unwrap(), 10), "dawn");
+        assert_eq!(time_at(sun.sunrise.unwrap(), 70), "morning");
+        assert_eq!(time_at(sun.noon, 0), "noon");
+        assert_eq!(time_at(sun.sunset.unwrap(), -30), "dusk");
+        assert_eq!(time_at(sun.civil_dusk.unwrap(), 10), "evening");
+        assert_eq!(time_at(sun.astronomical_dusk.unwrap(), 10), "night");
+    }
+
+    fn local_offset_degrees(date: chrono::NaiveDate) -> f64 {
+        let noon = Local.from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap()).unwrap();
+        noon.offset().local_minus_utc() as f64 / 3600.0 * 15.0
+    }
+
+    #[test]
+    fn nights_without_astronomical_dusk_start_after_civil_dusk() {
+        // at 51.5N in june the sun never gets 18 degrees below the horizon
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 6, 21).unwrap();
+        let (latitude, longitude) = (51.5, local_offset_degrees(date));
+        let time_data = TimeData::new(TimePeriodConfig { latitude: Some(latitude), longitude: Some(longitude), ..TimePeriodConfig::default() });
+
+        let sun = solar::sun_times(date, latitude, longitude);
+        assert!(sun.astronomical_dusk.is_none());
+        let time_at = |time: DateTime<Utc>, minutes: i64| time_data.get_time_at((time + chrono::Duration::minutes(minutes)).with_timezone(&Local)).vibe_name();
+
+        assert_eq!(time_at(sun.civil_dawn.unwrap(), 10), "dawn");
+        assert_eq!(time_at(sun.sunset.unwrap(), -30), "dusk");
+        assert_eq!(time_at(sun.civil_dusk.unwrap(), 30), "evening");
+        assert_eq!(time_at(sun.civil_dusk.unwrap(), 70), "night");
+    }
+
+    #[test]
+    fn follows_the_sun_across_local_midnight() {
+        // half a world away from the local timezone the solar day straddles midnight on the local clock
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+        let (latitude, longitude) = (21.0, (local_offset_degrees(date) + 360.0) % 360.0 - 180.0);
+        let time_data = TimeData::new(TimePeriodConfig { latitude: Some(latitude), longitude: Some(longitude), ..TimePeriodConfig::default() });
+
+        let sun = solar::sun_times(date, latitude, longitude);
+        let time_at = |time: DateTime<Utc>, minutes: i64| time_data.get_time_at((time + chrono::Duration::minutes(minutes)).with_timezone(&Local)).vibe_name();
+
+        assert_eq!(time_at(sun.civil_dawn.unwrap(), 10), "dawn");
+        assert_eq!(time_at(sun.sunrise.unwrap(), 70), "morning");
+        assert_eq!(time_at(sun.noon, 0), "noon");
+        assert_eq!(time_at(sun.sunset.unwrap(), -30), "dusk");
+        assert_eq!(time_at(sun.civil_dusk.unwrap(), 10), "evening");
+        assert_eq!(time_at(sun.astronomical_dusk.unwrap(), 10), "night");
+    }
+
+    #[test]
+    fn keeps_fixed_hours_without_a_usable_sun() {
+        let time_data = TimeData::new(TimePeriodConfig { latitude: Some(69.6492), longitude: Some(18.9553), ..TimePeriodConfig::default() });
+        let midsummer = Local.with_ymd_and_hms(2025, 6, 21, 12, 0, 0).unwrap();
+
+        let periods = time_data.get_periods_at(midsummer);
+        assert_eq!((periods.dawn, periods.night), (5.0, 22.0));
+        assert_eq!(blend_at(&time_data, 7, 0), [("morning", 0.5), ("dawn", 0.5)]);
+    }
+
+    #[test]
+    fn names_a_context_by_its_vibes() {
+        let context = Context::from_vibes("night", "winter", "rainy,coldy").unwrap();
+        assert_eq!((context.time.vibe_name(), context.season.vibe_name(), context.weather.vibe_name().as_str()), ("night", "winter", "rainy,coldy"));
+        assert!(Context::from_vibes("teatime", "winter", "rainy").is_none());
+        assert!(Context::from_vibes("night", "winter", "drizzly").is_none());
+    }
+
+    #[test]
+    fn parses_the_hourly_forecast() {
+        let response = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecast/wttr_rain_at_noon.json")).unwrap();
+        let forecast = WeatherData::parse_forecast(&response, &WeatherMapping::wttr(), &WeatherThresholds::default()).unwrap();
+
+        assert_eq!(forecast.len(), 16);
+        assert_eq!(forecast[0].start, Local.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap());
+        let at = |hour, minute| {
+            let at = Local.with_ymd_and_hms(2025, 6, 1, hour, minute, 0).unwrap();
+            forecast_at(&forecast, at).map(|hour| hour.weather.vibe_name())
+        };
+        assert_eq!(at(11, 59).as_deref(), Some("cloudy"));
+        assert_eq!(at(12, 0).as_deref(), Some("rainy"));
+        assert_eq!(at(16, 30).as_deref(), Some("rainy"));
+        assert!(forecast_at(&forecast, Local.with_ymd_and_hms(2025, 5, 31, 23, 0, 0).unwrap()).is_none());
+    }
+
+    #[test]
+    fn lists_the_boundaries_between_two_times() {
+        let time_data = TimeData::default();
+        let from = Local.with_ymd_and_hms(2025, 6, 1, 16, 30, 0).unwrap();
+        let hours: Vec<_> = time_data.get_boundaries_between(from, from + chrono::Duration::hours(15))
+            .iter()
+            .map(|boundary| boundary.format("%d %H:%M").to_string())
+            .collect();
+
+        assert_eq!(hours, ["01 17:00", "01 19:00", "01 22:00", "02 00:00", "02 05:00", "02 07:00"]);
+    }
 }
@@ -1,13 +1,40 @@
-use std::hash::{Hash, Hasher};
+use std::{collections::{HashMap, HashSet}, hash::{Hash, Hasher}, path::Path, sync::atomic::{AtomicBool, AtomicUsize, Ordering}, time::Duration};
 
+use chrono::{DateTime, Local, TimeZone};
 use serde::{Deserialize, Serialize};
 use sqlx::{prelude::FromRow, Row, SqlitePool};
 
+use crate::{audio_services::Metadata, data_collector::{Context, WeatherSet}, error::VibingError, events::{EventBus, VibingEvent}};
+
 #[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
 pub struct TrackHeader {
     pub id: i64,
     pub path: String,
-    pub vibes: Vec<Vibe>
+    pub vibes: Vec<Vibe>,
+    pub metadata: Metadata,
+}
+
+impl TrackHeader {
+    // "artist - title" when the tags are known, the file name otherwise
+    pub fn display_name(&self) -> String {
+        match (&self.metadata.artist, &self.metadata.title) {
+            (Some(artist), Some(title)) => format!("{artist} - {title}"),
+            (None, Some(title)) => title.clone(),
+            _ => Path::new(&self.path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.path.clone()),
+        }
+    }
+}
+
+fn metadata_from(title: Option<String>, artist: Option<String>, album: Option<String>, duration_secs: Option<f64>) -> Metadata {
+    Metadata {
+        title,
+        artist,
+        album,
+        duration: duration_secs.map(Duration::from_secs_f64),
+    }
 }
 
 impl PartialEq for TrackHeader {
@@ -36,18 +63,211 @@ pub struct VibeGroup {
     pub vibes: Vec<Vibe>
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayOutcome {
+    // played to the end
+    Completed,
+    // next() before the end
+    Skipped,
+    // previous() before the end, the track is queued again right after the one before it
+    Rewound,
+    // playback stopped or the player went away
+    Stopped,
+}
+
+impl PlayOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayOutcome::Completed => "completed",
+            PlayOutcome::Skipped => "skipped",
+            PlayOutcome::Rewound => "rewound",
+            PlayOutcome::Stopped => "stopped",
+        }
+    }
+
+    pub fn from_name(outcome: &str) -> Option<Self> {
+        match outcome {
+            "completed" => Some(PlayOutcome::Completed),
+            "skipped" => Some(PlayOutcome::Skipped),
+            "rewound" => Some(PlayOutcome::Rewound),
+            "stopped" => Some(PlayOutcome::Stopped),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Feedback {
+    Like,
+    Dislike,
+    Skip,
+}
+
+impl Feedback {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feedback::Like => "like",
+            Feedback::Dislike => "dislike",
+            Feedback::Skip => "skip",
+        }
+    }
+
+    pub fn from_name(feedback: &str) -> Option<Self> {
+        match feedback {
+            "like" => Some(Feedback::Like),
+            "dislike" => Some(Feedback::Dislike),
+            "skip" => Some(Feedback::Skip),
+            _ => None,
+        }
+    }
+}
+
+// feedback on a track, each entry counted by how much of its context matches the one asked about
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedbackTally {
+    pub track_id: i64,
+    pub likes: f32,
+    pub dislikes: f32,
+    pub skips: f32,
+}
+
+// a track carrying some of the vibes asked for, score is the sum of their weights
+#[derive(Debug, Clone)]
+pub struct RankedTrack {
+    pub track: TrackHeader,
+    pub score: f32,
+    // in the order the vibes were asked for
+    pub matched_vibes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayRecord {
+    pub track_id: i64,
+    pub started_at: DateTime<Local>,
+    pub listened: Duration,
+    pub outcome: PlayOutcome,
+    // vibe names of the context at play time, None when it wasn't known yet
+    pub time: Option<String>,
+    pub season: Option<String>,
+    pub weather: Option<String>,
+}
+
+// a filter over the vibes of a track, e.g. (rainy OR cloudy) AND evening AND NOT winter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VibeQuery {
+    Vibe(String),
+    // an empty All matches every track, an empty Any matches none
+    All(Vec<VibeQuery>),
+    Any(Vec<VibeQuery>),
+    Not(Box<VibeQuery>),
+}
+
+impl VibeQuery {
+    pub fn vibe(name: &str) -> Self {
+        VibeQuery::Vibe(name.to_string())
+    }
+
+    pub fn all(queries: impl IntoIterator<Item = VibeQuery>) -> Self {
+        VibeQuery::All(queries.into_iter().collect())
+    }
+
+    pub fn any(queries: impl IntoIterator<Item = VibeQuery>) -> Self {
+        VibeQuery::Any(queries.into_iter().collect())
+    }
+
+    // appends a condition on tp.track_id, vibe names are left as placeholders and pushed to params in order
+    pub fn to_sql<'a>(&'a self, sql: &mut String, params: &mut Vec<&'a str>) {
+        match self {
+            VibeQuery::Vibe(name) => {
+                sql.push_str(
+                    "EXISTS (SELECT 1 FROM track_vibes AS tv JOIN vibes AS vb ON vb.vibe_id = tv.vibe_id \
+                     WHERE tv.track_id = tp.track_id AND vb.name = ?)"
+                );
+                params.push(name);
+            }
+            VibeQuery::All(queries) | VibeQuery::Any(queries) => {
+                let (operator, empty) = match self {
+                    VibeQuery::All(_) => (" AND ", "1"),
+                    _ => (" OR ", "0"),
+                };
+                if queries.is_empty() {
+                    sql.push_str(empty);
+                    return;
+                }
+
+                sql.push('(');
+                for (i, query) in queries.iter().enumerate() {
+                    if i > 0 {
+                        sql.push_str(operator);
+                    }
+                    query.to_sql(sql, params);
+                }
+                sql.push(')');
+            }
+            VibeQuery::Not(query) => {
+                sql.push_str("NOT ");
+                query.to_sql(sql, params);
+            }
+        }
+    }
+}
+
+// !VibeQuery::vibe("winter")
+impl std::ops::Not for VibeQuery {
+    type Output = VibeQuery;
+
+    fn not(self) -> Self::Output {
+        VibeQuery::Not(Box::new(self))
+    }
+}
+
 pub struct Mp3Database {
     pool: SqlitePool,
+    events: EventBus,
+    // open begin_batch() calls, and whether anything changed since the first one
+    batches: AtomicUsize,
+    changed_in_batch: AtomicBool,
 }
 
 impl Mp3Database {
-    pub async fn new(database_url: &str) -> Result<Self, sqlx::Error> {
+    pub async fn new(database_url: &str) -> Result<Self, VibingError> {
         let pool = SqlitePool::connect(database_url).await?;
-        Ok( Self { pool } )
+        Ok( Self { pool, events: EventBus::default(), batches: AtomicUsize::new(0), changed_in_batch: AtomicBool::new(false) } )
+    }
+
+    // mutations publish VibingEvent::LibraryUpdated on this bus
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    fn library_updated(&self) {
+        if self.batches.load(Ordering::SeqCst) > 0 {
+            self.changed_in_batch.store(true, Ordering::SeqCst);
+        } else {
+            self.events.publish(VibingEvent::LibraryUpdated);
+        }
+    }
+
+    // bulk changes like a scan hold LibraryUpdated back until the matching end_batch(),
+    // which publishes it once if anything changed. a burst of them would overrun the bus
+    pub fn begin_batch(&self) {
+        self.batches.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn end_batch(&self) {
+        if self.batches.fetch_sub(1, Ordering::SeqCst) == 1 && self.changed_in_batch.swap(false, Ordering::SeqCst) {
+            self.events.publish(VibingEvent::LibraryUpdated);
+        }
+    }
+
+    pub async fn migrate(&self) -> Result<(), VibingError> {
+        sqlx::migrate!("./migrations").run(&self.pool).await?;
+        Ok(())
     }
 
     // CREATE TRACK
-    pub async fn add_track(&self, path: &str) -> Result<i64, sqlx::Error> {
+    pub async fn add_track(&self, path: &str) -> Result<i64, VibingError> {
         let id = sqlx::query!(
             "
             INSERT INTO track_pointers (path)
@@ -58,28 +278,33 @@ impl Mp3Database {
             .await?
             .track_id;
 
+        self.library_updated();
         Ok(id)
     }
 
     // READ TRACK
-    pub async fn get_track_header(&self, track_id: i64) -> Result<Option<TrackHeader>, sqlx::Error> {
+    pub async fn get_track_header(&self, track_id: i64) -> Result<Option<TrackHeader>, VibingError> {
         let result = sqlx::query!(
-            "
-            SELECT track_id AS id, path
-            FROM track_pointers
-            WHERE track_id = ?
-            ", track_id)
+            r#"
+            SELECT tp.track_id AS id, tp.path,
+                   tm.title AS "title?", tm.artist AS "artist?", tm.album AS "album?",
+                   tm.duration_secs AS "duration_secs?"
+            FROM track_pointers AS tp
+            LEFT JOIN track_metadata AS tm ON tm.track_id = tp.track_id
+            WHERE tp.track_id = ?
+            "#, track_id)
             .fetch_optional(&self.pool)
             .await?;
             
-        if result.is_none() {
+        let Some(record) = result else {
             return Ok(None);
-        }
+        };
 
         let mut track_header = TrackHeader {
             id: track_id,
-            path: result.unwrap().path,
+            path: record.path,
             vibes: Vec::new(),
+            metadata: metadata_from(record.title, record.artist, record.album, record.duration_secs),
         };
 
         let vibes = self.get_vibes_for_track(track_id).await?;
@@ -92,30 +317,105 @@ impl Mp3Database {
     }
 
     // READ TRACKS
-    pub async fn get_all_tracks(&self) -> Result<Vec<TrackHeader>, sqlx::Error> {
-        let mut track_headers = Vec::new();
+    pub async fn get_all_tracks(&self) -> Result<Vec<TrackHeader>, VibingError> {
+        self.get_tracks_page(0, i64::MAX).await
+    }
+
+    // keyset pagination, pass the id of the last track of the previous page (0 for the first page)
+    pub async fn get_tracks_page(&self, after_id: i64, limit: i64) -> Result<Vec<TrackHeader>, VibingError> {
+        let records = sqlx::query!(
+            r#"
+            SELECT tp.track_id, tp.path,
+                   tm.title AS "title?", tm.artist AS "artist?", tm.album AS "album?",
+                   tm.duration_secs AS "duration_secs?"
+            FROM track_pointers AS tp
+            LEFT JOIN track_metadata AS tm ON tm.track_id = tp.track_id
+            WHERE tp.track_id > ?
+            ORDER BY tp.track_id ASC
+            LIMIT ?
+            "#, after_id, limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut track_headers: Vec<_> = records.into_iter()
+            .map(|record| TrackHeader {
+                id: record.track_id,
+                path: record.path,
+                vibes: Vec::new(),
+                metadata: metadata_from(record.title, record.artist, record.album, record.duration_secs),
+            })
+            .collect();
+
+        self.load_vibes(&mut track_headers).await?;
+        Ok(track_headers)
+    }
+
+    // the tracks stored under these paths in the given order, unknown paths are left out
+    pub async fn get_tracks_by_paths(&self, paths: &[String]) -> Result<Vec<TrackHeader>, VibingError> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let paths = serde_json::to_string(paths)?;
+        let records = sqlx::query!(
+            r#"
+            SELECT tp.track_id AS "track_id!", tp.path AS "path!",
+                   tm.title AS "title?", tm.artist AS "artist?", tm.album AS "album?",
+                   tm.duration_secs AS "duration_secs?"
+            FROM json_each(?) AS playlist
+            JOIN track_pointers AS tp ON tp.path = playlist.value
+            LEFT JOIN track_metadata AS tm ON tm.track_id = tp.track_id
+            ORDER BY playlist.key
+            "#, paths)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut track_headers: Vec<_> = records.into_iter()
+            .map(|record| TrackHeader {
+                id: record.track_id,
+                path: record.path,
+                vibes: Vec::new(),
+                metadata: metadata_from(record.title, record.artist, record.album, record.duration_secs),
+            })
+            .collect();
+
+        self.load_vibes(&mut track_headers).await?;
+        Ok(track_headers)
+    }
+
+    // fills in the vibes of all the given tracks with a single query
+    async fn load_vibes(&self, tracks: &mut [TrackHeader]) -> Result<(), VibingError> {
+        if tracks.is_empty() {
+            return Ok(());
+        }
 
+        // one json array parameter instead of one placeholder per id, so big pages don't hit the variable limit
+        let track_ids = serde_json::to_string(&tracks.iter().map(|track| track.id).collect::<Vec<_>>())?;
         let records = sqlx::query!(
             "
-            SELECT track_id, path
-            FROM track_pointers
-            ORDER BY track_id ASC
-            ")
+            SELECT tv.track_id, vb.name AS name, vg.name AS group_name
+            FROM track_vibes AS tv
+            JOIN vibes AS vb ON vb.vibe_id = tv.vibe_id
+            JOIN vibe_groups AS vg ON vb.vibe_group_id = vg.vibe_group_id
+            WHERE tv.track_id IN (SELECT value FROM json_each(?))
+            ORDER BY tv.track_id, tv.vibe_id
+            ", track_ids)
             .fetch_all(&self.pool)
             .await?;
-    
+
+        let mut vibes: HashMap<i64, Vec<Vibe>> = HashMap::new();
         for record in records {
-            let vibes = self.get_vibes_for_track(record.track_id).await?;
-            track_headers.push(
-                TrackHeader { id: record.track_id, path: record.path, vibes }
-            );
+            vibes.entry(record.track_id).or_default().push(Vibe { name: record.name, group_name: record.group_name });
+        }
+        for track in tracks {
+            track.vibes = vibes.remove(&track.id).unwrap_or_default();
         }
 
-        Ok(track_headers)
+        Ok(())
     }
 
     // UPDATE TRACK
-    pub async fn update_track_path(&self, track_id: i64, path: &str) -> Result<(), sqlx::Error> {
+    pub async fn update_track_path(&self, track_id: i64, path: &str) -> Result<(), VibingError> {
         sqlx::query!(
             "
             UPDATE track_pointers
@@ -125,11 +425,71 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
+        Ok(())
+    }
+
+    // UPDATE METADATA
+    pub async fn set_track_metadata(&self, track_id: i64, metadata: &Metadata) -> Result<(), VibingError> {
+        let duration_secs = metadata.duration.map(|duration| duration.as_secs_f64());
+        sqlx::query!(
+            "
+            INSERT INTO track_metadata (track_id, title, artist, album, duration_secs)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (track_id) DO UPDATE SET
+                title = excluded.title,
+                artist = excluded.artist,
+                album = excluded.album,
+                duration_secs = excluded.duration_secs,
+                updated_at = CURRENT_TIMESTAMP
+            ", track_id, metadata.title, metadata.artist, metadata.album, duration_secs)
+            .execute(&self.pool)
+            .await?;
+
+        self.library_updated();
         Ok(())
     }
 
+    // re-read the tags of the file, no audio device involved
+    pub async fn refresh_metadata(&self, track_id: i64) -> Result<Metadata, VibingError> {
+        let path = sqlx::query!(
+            "
+            SELECT path
+            FROM track_pointers
+            WHERE track_id = ?
+            ", track_id)
+            .fetch_one(&self.pool)
+            .await?
+            .path;
+
+        let metadata = Metadata::read_from_path(&path)?;
+        self.set_track_metadata(track_id, &metadata).await?;
+
+        Ok(metadata)
+    }
+
+    // returns the tracks whose tags could not be read
+    pub async fn refresh_all_metadata(&self) -> Result<Vec<(TrackHeader, VibingError)>, VibingError> {
+        let mut failed = Vec::new();
+
+        self.begin_batch();
+        for track in self.get_all_tracks().await? {
+            match self.refresh_metadata(track.id).await {
+                Ok(_) => {}
+                Err(e @ VibingError::Sqlx(_)) => {
+                    self.end_batch();
+                    return Err(e);
+                }
+                Err(e) => failed.push((track, e)),
+            }
+        }
+        self.end_batch();
+
+        Ok(failed)
+    }
+
     // DELETE TARCK
-    pub async fn remove_track(&self, track_id: i64) -> Result<(), sqlx::Error> {
+    pub async fn remove_track(&self, track_id: i64) -> Result<(), VibingError> {
         sqlx::query!(
             "
             DELETE FROM track_pointers
@@ -138,11 +498,12 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
     // CREATE GROUP
-    pub async fn add_vibe_group(&self, name: &str) -> Result<i64, sqlx::Error> {
+    pub async fn add_vibe_group(&self, name: &str) -> Result<i64, VibingError> {
         let id = sqlx::query!(
             "
             INSERT INTO vibe_groups (name)
@@ -153,11 +514,12 @@ impl Mp3Database {
             .await?
             .vibe_group_id;
 
+        self.library_updated();
         Ok(id)
     }
 
     // READ GROUP
-    pub async fn get_vibe_group(&self, name: &str) -> Result<VibeGroup, sqlx::Error> {
+    pub async fn get_vibe_group(&self, name: &str) -> Result<VibeGroup, VibingError> {
         let record = sqlx::query!(
             "
             SELECT name
@@ -180,28 +542,48 @@ impl Mp3Database {
     }
 
     // READ GROUPS
-    pub async fn get_all_vibe_groups(&self) -> Result<Vec<VibeGroup>, sqlx::Error> {
-        let mut groups = Vec::new();
+    pub async fn get_all_vibe_groups(&self) -> Result<Vec<VibeGroup>, VibingError> {
         let records = sqlx::query!(
-            "
-            SELECT name
-            FROM vibe_groups
-            ")
+            r#"
+            SELECT vg.name AS group_name, vb.name AS "name?"
+            FROM vibe_groups AS vg
+            LEFT JOIN vibes AS vb ON vb.vibe_group_id = vg.vibe_group_id
+            ORDER BY vg.vibe_group_id, vb.vibe_id
+            "#)
             .fetch_all(&self.pool)
             .await?;
 
+        let mut groups: Vec<VibeGroup> = Vec::new();
         for record in records {
-            let vibes = self.get_vibes_in_group(&record.name).await?;
-            groups.push(
-                VibeGroup { name: record.name, vibes }
-            );
+            if groups.last().is_none_or(|group| group.name != record.group_name) {
+                groups.push(VibeGroup { name: record.group_name.clone(), vibes: Vec::new() });
+            }
+            // a group without vibes still comes back once, with no vibe
+            if let Some(name) = record.name {
+                groups.last_mut().unwrap().vibes.push(Vibe { name, group_name: record.group_name });
+            }
         }
 
         Ok(groups)
     }
 
+    // the names that have no row in the vibes table, in the order given
+    pub async fn get_missing_vibes(&self, names: &[&str]) -> Result<Vec<String>, VibingError> {
+        let known: HashSet<String> = sqlx::query!("SELECT name FROM vibes")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|record| record.name)
+            .collect();
+
+        Ok(names.iter()
+            .filter(|name| !known.contains(**name))
+            .map(|name| name.to_string())
+            .collect())
+    }
+
     // UPDATE GROUP
-    pub async fn change_vibe_group_name(&self, old_name: &str, new_name: &str) -> Result<(), sqlx::Error> {
+    pub async fn change_vibe_group_name(&self, old_name: &str, new_name: &str) -> Result<(), VibingError> {
         sqlx::query!(
             "
             UPDATE vibe_groups
@@ -211,11 +593,12 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
     // DELETE GROUP
-    pub async fn remove_vibe_group(&self, name: &str) -> Result<(), sqlx::Error> {
+    pub async fn remove_vibe_group(&self, name: &str) -> Result<(), VibingError> {
         sqlx::query!(
             "
             DELETE FROM vibe_groups
@@ -224,11 +607,12 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
     // CREATE VIBE
-    pub async fn add_vibe(&self, name: &str, group_name: &str) -> Result<i64, sqlx::Error> {
+    pub async fn add_vibe(&self, name: &str, group_name: &str) -> Result<i64, VibingError> {
         let group_id_result = sqlx::query!(
             "
             SELECT vibe_group_id AS id
@@ -248,15 +632,16 @@ impl Mp3Database {
                 ", name, group_id)
                 .fetch_one(&self.pool)
                 .await?;
+            self.library_updated();
             Ok(record.vibe_id.unwrap())
         } else {
-            Err(sqlx::Error::RowNotFound)
+            Err(sqlx::Error::RowNotFound.into())
         }
     }
 
     // READ VIBE
-    pub async fn get_vibe(&self, name: &str) -> Result<Vibe, sqlx::Error> {
-        sqlx::query_as!(Vibe,
+    pub async fn get_vibe(&self, name: &str) -> Result<Vibe, VibingError> {
+        Ok(sqlx::query_as!(Vibe,
             "
             SELECT vb.name AS name, vg.name AS group_name
             FROM vibes AS vb
@@ -264,11 +649,11 @@ impl Mp3Database {
             WHERE vb.name = ?
             ", name)
             .fetch_one(&self.pool)
-            .await
+            .await?)
     }
 
     // UPDATE VIBE
-    pub async fn change_vibe_name(&self, old_name: &str, new_name: &str) -> Result<(), sqlx::Error> {
+    pub async fn change_vibe_name(&self, old_name: &str, new_name: &str) -> Result<(), VibingError> {
         sqlx::query!(
             "
             UPDATE vibes
@@ -278,11 +663,12 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
     // DELETE VIBE
-    pub async fn remove_vibe(&self, name: &str) -> Result<(), sqlx::Error> {
+    pub async fn remove_vibe(&self, name: &str) -> Result<(), VibingError> {
         sqlx::query!(
             "
             DELETE FROM vibes
@@ -291,10 +677,11 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
-    pub async fn associate_vibe_with_track(&self, track_id: i64, vibe_name: &str) -> Result<(), sqlx::Error> {
+    pub async fn associate_vibe_with_track(&self, track_id: i64, vibe_name: &str) -> Result<(), VibingError> {
         let vibe_id = sqlx::query!(
             "
             SELECT vibe_id
@@ -316,10 +703,11 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
-    pub async fn disassociate_vibe_with_track(&self, track_id: i64, vibe_name: &str) -> Result<(), sqlx::Error> {
+    pub async fn disassociate_vibe_with_track(&self, track_id: i64, vibe_name: &str) -> Result<(), VibingError> {
         let vibe_id = sqlx::query!(
             "
             SELECT vibe_id
@@ -341,10 +729,11 @@ impl Mp3Database {
             .execute(&self.pool)
             .await?;
 
+        self.library_updated();
         Ok(())
     }
 
-    pub async fn get_vibes_for_track(&self, track_id: i64) -> Result<Vec<Vibe>, sqlx::Error> {
+    pub async fn get_vibes_for_track(&self, track_id: i64) -> Result<Vec<Vibe>, VibingError> {
         Ok(sqlx::query_as!(Vibe,
             "
             SELECT vb.name AS name, vg.name AS group_name
@@ -357,8 +746,8 @@ impl Mp3Database {
             .await?)
     }
 
-    pub async fn get_vibes_in_group(&self, name: &str) -> Result<Vec<Vibe>, sqlx::Error> {
-        sqlx::query_as!(Vibe,
+    pub async fn get_vibes_in_group(&self, name: &str) -> Result<Vec<Vibe>, VibingError> {
+        Ok(sqlx::query_as!(Vibe,
             "
             SELECT vb.name AS name, vg.name AS group_name
             FROM vibes AS vb
@@ -366,45 +755,521 @@ impl Mp3Database {
             WHERE vg.name = ?
             ", name)
             .fetch_all(&self.pool)
-            .await
+            .await?)
     }
 
-    pub async fn get_tracks_by_vibes(&self, vibe_names: &[&str]) -> Result<Vec<TrackHeader>, sqlx::Error> {
+    // tracks carrying all of the given vibes
+    pub async fn get_tracks_by_vibes(&self, vibe_names: &[&str]) -> Result<Vec<TrackHeader>, VibingError> {
         if vibe_names.is_empty() {
             return Ok(Vec::new());
         }
 
-        let mut tracks = Vec::new();
+        self.get_tracks_matching(&VibeQuery::all(vibe_names.iter().map(|name| VibeQuery::vibe(name)))).await
+    }
+
+    pub async fn get_tracks_matching(&self, vibe_query: &VibeQuery) -> Result<Vec<TrackHeader>, VibingError> {
+        let mut condition = String::new();
+        let mut params = Vec::new();
+        vibe_query.to_sql(&mut condition, &mut params);
 
         let query_str = format!(
             "
-            SELECT tp.track_id AS id, tp.path AS path
+            SELECT tp.track_id AS id, tp.path AS path,
+                   tm.title AS title, tm.artist AS artist, tm.album AS album,
+                   tm.duration_secs AS duration_secs
             FROM track_pointers AS tp
-            INNER JOIN track_vibes AS tv ON tp.track_id = tv.track_id
-            INNER JOIN vibes AS vb on tv.vibe_id = vb.vibe_id
-            WHERE vb.name IN ({})
-            GROUP BY tp.track_id, tp.path
-            HAVING COUNT(DISTINCT vb.name) = {}
-            ",
-            vibe_names.iter().map(|_| "?").collect::<Vec<_>>().join(","),
-            vibe_names.len()
+            LEFT JOIN track_metadata AS tm ON tm.track_id = tp.track_id
+            WHERE {condition}
+            ORDER BY tp.track_id ASC
+            "
         );
 
         let mut query = sqlx::query(&query_str);
-        for name in vibe_names {
+        for name in params {
             query = query.bind(name);
         }
 
-        let tracks_db = query.fetch_all(&self.pool).await?;
+        let mut tracks: Vec<_> = query.fetch_all(&self.pool).await?
+            .into_iter()
+            .map(|track| {
+                let metadata = metadata_from(
+                    track.get("title"), track.get("artist"), track.get("album"), track.get("duration_secs")
+                );
+                TrackHeader { id: track.get("id"), path: track.get("path"), vibes: Vec::new(), metadata }
+            })
+            .collect();
+
+        self.load_vibes(&mut tracks).await?;
+        Ok(tracks)
+    }
+
+    // PLAY HISTORY
+    pub async fn record_play(&self, play: &PlayRecord) -> Result<i64, VibingError> {
+        let started_at = play.started_at.timestamp();
+        let listened_secs = play.listened.as_secs_f64();
+        let outcome = play.outcome.as_str();
+
+        Ok(sqlx::query!(
+            r#"
+            INSERT INTO play_history (track_id, started_at, listened_secs, outcome, time_vibe, season_vibe, weather_vibe)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING play_id AS "play_id!"
+            "#, play.track_id, started_at, listened_secs, outcome, play.time, play.season, play.weather)
+            .fetch_one(&self.pool)
+            .await?
+            .play_id)
+    }
+
+    // newest first
+    pub async fn get_recent_plays(&self, limit: i64) -> Result<Vec<PlayRecord>, VibingError> {
+        let records = sqlx::query!(
+            "
+            SELECT track_id, started_at, listened_secs, outcome, time_vibe, season_vibe, weather_vibe
+            FROM play_history
+            ORDER BY started_at DESC, play_id DESC
+            LIMIT ?
+            ", limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        records.into_iter().map(|record| Ok(PlayRecord {
+            track_id: record.track_id,
+            started_at: Local.timestamp_opt(record.started_at, 0).single()
+                .ok_or_else(|| VibingError::Parse(format!("invalid play time: {}", record.started_at)))?,
+            listened: Duration::from_secs_f64(record.listened_secs.max(0.0)),
+            outcome: PlayOutcome::from_name(&record.outcome)
+                .ok_or_else(|| VibingError::Parse(format!("invalid play outcome: {}", record.outcome)))?,
+            time: record.time_vibe,
+            season: record.season_vibe,
+            weather: record.weather_vibe,
+        })).collect()
+    }
+
+    // when each track was last played, for the tracks played since the given time
+    pub async fn get_last_played_since(&self, since: DateTime<Local>) -> Result<Vec<(i64, DateTime<Local>)>, VibingError> {
+        let since = since.timestamp();
+        let records = sqlx::query!(
+            r#"
+            SELECT track_id AS "track_id!", MAX(started_at) AS "started_at!: i64"
+            FROM play_history
+            WHERE started_at >= ?
+            GROUP BY track_id
+            "#, since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        records.into_iter().map(|record| {
+            let started_at = Local.timestamp_opt(record.started_at, 0).single()
+                .ok_or_else(|| VibingError::Parse(format!("invalid play time: {}", record.started_at)))?;
+            Ok((record.track_id, started_at))
+        }).collect()
+    }
+
+    pub async fn get_most_played(&self, limit: i64) -> Result<Vec<(TrackHeader, i64)>, VibingError> {
+        self.get_play_counts(None, limit).await
+    }
+
+    pub async fn get_most_skipped(&self, limit: i64) -> Result<Vec<(TrackHeader, i64)>, VibingError> {
+        self.get_play_counts(Some(PlayOutcome::Skipped), limit).await
+    }
+
+    async fn get_play_counts(&self, outcome: Option<PlayOutcome>, limit: i64) -> Result<Vec<(TrackHeader, i64)>, VibingError> {
+        let outcome = outcome.map(|outcome| outcome.as_str());
+        let records = sqlx::query!(
+            r#"
+            SELECT tp.track_id, tp.path,
+                   tm.title AS "title?", tm.artist AS "artist?", tm.album AS "album?",
+                   tm.duration_secs AS "duration_secs?",
+                   COUNT(*) AS "plays!: i64"
+            FROM play_history AS ph
+            JOIN track_pointers AS tp ON tp.track_id = ph.track_id
+            LEFT JOIN track_metadata AS tm ON tm.track_id = tp.track_id
+            WHERE ?1 IS NULL OR ph.outcome = ?1
+            GROUP BY tp.track_id
+            ORDER BY "plays!: i64" DESC, tp.track_id ASC
+            LIMIT ?2
+            "#, outcome, limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let counts: Vec<_> = records.iter().map(|record| record.plays).collect();
+        let mut tracks: Vec<_> = records.into_iter()
+            .map(|record| TrackHeader {
+                id: record.track_id,
+                path: record.path,
+                vibes: Vec::new(),
+                metadata: metadata_from(record.title, record.artist, record.album, record.duration_secs),
+            })
+            .collect();
+        self.load_vibes(&mut tracks).await?;
+
+        Ok(tracks.into_iter().zip(counts).collect())
+    }
+
+    // time listened to tracks carrying each vibe, most listened first
+    pub async fn get_listening_time_by_vibe(&self) -> Result<Vec<(String, Duration)>, VibingError> {
+        let records = sqlx::query!(
+            r#"
+            SELECT vb.name, SUM(ph.listened_secs) AS "listened_secs!: f64"
+            FROM play_history AS ph
+            JOIN track_vibes AS tv ON tv.track_id = ph.track_id
+            JOIN vibes AS vb ON vb.vibe_id = tv.vibe_id
+            GROUP BY vb.vibe_id
+            ORDER BY "listened_secs!: f64" DESC, vb.name ASC
+            "#)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(records.into_iter()
+            .map(|record| (record.name, Duration::from_secs_f64(record.listened_secs.max(0.0))))
+            .collect())
+    }
+
+    // FEEDBACK
+    pub async fn record_feedback(&self, track_id: i64, feedback: Feedback, context: Option<Context>) -> Result<i64, VibingError> {
+        let feedback = feedback.as_str();
+        let created_at = Local::now().timestamp();
+        let time = context.map(|context| context.time.vibe_name());
+        let season = context.map(|context| context.season.vibe_name());
+        let weather = context.map(|context| context.weather.vibe_name());
+
+        Ok(sqlx::query!(
+            r#"
+            INSERT INTO track_feedback (track_id, feedback, created_at, time_vibe, season_vibe, weather_vibe)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING feedback_id AS "feedback_id!"
+            "#, track_id, feedback, created_at, time, season, weather)
+            .fetch_one(&self.pool)
+            .await?
+            .feedback_id)
+    }
+
+    // feedback given in a context with these vibes counts fully, a third less for every part of it
+    // that differs. weathers are sets, they match when they share a vibe: a skip on a rainy cold
+    // morning still counts fully on a rainy one
+    pub async fn get_feedback_for(&self, vibes: &[String]) -> Result<Vec<FeedbackTally>, VibingError> {
+        let vibes = serde_json::to_string(vibes)?;
+
+        let records = sqlx::query!(
+            r#"
+            SELECT track_id AS "track_id!",
+                   SUM(CASE feedback WHEN 'like' THEN relevance ELSE 0 END) AS "likes!: f64",
+                   SUM(CASE feedback WHEN 'dislike' THEN relevance ELSE 0 END) AS "dislikes!: f64",
+                   SUM(CASE feedback WHEN 'skip' THEN relevance ELSE 0 END) AS "skips!: f64"
+            FROM (
+                SELECT track_id, feedback,
+                       ((time_vibe IS NULL OR time_vibe IN (SELECT value FROM json_each(?1)))
+                        + (season_vibe IS NULL OR season_vibe IN (SELECT value FROM json_each(?1)))
+                        + (weather_vibe IS NULL OR EXISTS (
+                            SELECT 1 FROM json_each(?1) AS wanted
+                            WHERE instr(',' || weather_vibe || ',', ',' || wanted.value || ',') > 0
+                        ))) / 3.0 AS relevance
+                FROM track_feedback
+            )
+            GROUP BY track_id
+            ORDER BY track_id
+            "#, vibes)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(records.into_iter()
+            .map(|record| FeedbackTally {
+                track_id: record.track_id,
+                likes: record.likes as f32,
+                dislikes: record.dislikes as f32,
+                skips: record.skips as f32,
+            })
+            .collect())
+    }
+
+    // WEATHER CACHE
+    pub async fn cache_weather(&self, source: &str, weather: &WeatherSet, fetched_at: DateTime<Local>) -> Result<(), VibingError> {
+        let vibes = weather.vibe_name();
+        let temperature = weather.get_temperature() as f64;
+        let fetched_at = fetched_at.timestamp();
+
+        sqlx::query!(
+            "
+            INSERT INTO weather_cache (source, vibes, temperature, fetched_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (source) DO UPDATE
+            SET vibes = excluded.vibes, temperature = excluded.temperature, fetched_at = excluded.fetched_at
+            ", source, vibes, temperature, fetched_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
 
-        for track in tracks_db {
-            let vibes = self.get_vibes_for_track(track.get("id")).await?;
-            tracks.push(
-                TrackHeader { id: track.get("id"), path: track.get("path"), vibes }
+    // the last observation of the source and when it was fetched, None if it has none
+    // or its vibes are no longer known
+    pub async fn get_cached_weather(&self, source: &str) -> Result<Option<(WeatherSet, DateTime<Local>)>, VibingError> {
+        let record = sqlx::query!(
+            "
+            SELECT vibes, temperature, fetched_at
+            FROM weather_cache
+            WHERE source = ?
+            ", source)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(record.and_then(|record| {
+            let weather = WeatherSet::from_vibes(&record.vibes, record.temperature as f32)?;
+            let fetched_at = Local.timestamp_opt(record.fetched_at, 0).single()?;
+            Some((weather, fetched_at))
+        }))
+    }
+
+    // scores every track by the summed weight of the given vibes it carries, best first.
+    // matching, scoring and both vibe lists come back from a single statement
+    pub async fn rank_tracks_by_vibes(&self, weighted_vibes: &[(&str, f32)]) -> Result<Vec<RankedTrack>, VibingError> {
+        let mut wanted: Vec<(&str, f32)> = Vec::new();
+        for &(name, weight) in weighted_vibes {
+            match wanted.iter_mut().find(|(known, _)| *known == name) {
+                Some((_, total)) => *total += weight,
+                None => wanted.push((name, weight)),
+            }
+        }
+
+        if wanted.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // char(31) separates the fields of a vibe and char(30) the vibes of a track
+        let query_str = format!(
+            "
+            WITH wanted (name, weight, position) AS (VALUES {})
+            SELECT tp.track_id AS id, tp.path AS path,
+                   tm.title AS title, tm.artist AS artist, tm.album AS album,
+                   tm.duration_secs AS duration_secs,
+                   SUM(w.weight) AS score,
+                   group_concat(w.name, char(30) ORDER BY w.position) AS matched_vibes,
+                   (
+                       SELECT group_concat(all_vb.name || char(31) || all_vg.name, char(30))
+                       FROM track_vibes AS all_tv
+                       JOIN vibes AS all_vb ON all_vb.vibe_id = all_tv.vibe_id
+                       JOIN vibe_groups AS all_vg ON all_vg.vibe_group_id = all_vb.vibe_group_id
+                       WHERE all_tv.track_id = tp.track_id
+                   ) AS vibes
+            FROM wanted AS w
+            INNER JOIN vibes AS vb ON vb.name = w.name
+            INNER JOIN track_vibes AS tv ON tv.vibe_id = vb.vibe_id
+            INNER JOIN track_pointers AS tp ON tp.track_id = tv.track_id
+            LEFT JOIN track_metadata AS tm ON tm.track_id = tp.track_id
+            GROUP BY tp.track_id
+            ORDER BY score DESC, tp.track_id ASC
+            ",
+            (0..wanted.len()).map(|_| "(?, ?, ?)").collect::<Vec<_>>().join(",")
+        );
+
+        let mut query = sqlx::query(&query_str);
+        for (position, (name, weight)) in wanted.iter().enumerate() {
+            query = query.bind(*name).bind(*weight as f64).bind(position as i64);
+        }
+
+        let rows = query.fetch_all(&self.pool).await?;
+
+        Ok(rows.into_iter().map(|row| {
+            let vibes = row.get::<Option<String>, _>("vibes").unwrap_or_default()
+                .split('\u{1e}')
+                .filter_map(|vibe| vibe.split_once('\u{1f}'))
+                .map(|(name, group_name)| Vibe { name: name.to_string(), group_name: group_name.to_string() })
+                .collect();
+            let matched_vibes = row.get::<String, _>("matched_vibes")
+                .split('\u{1e}')
+                .map(str::to_string)
+                .collect();
+            let metadata = metadata_from(
+                row.get("title"), row.get("artist"), row.get("album"), row.get("duration_secs")
             );
+
+            RankedTrack {
+                track: TrackHeader { id: row.get("id"), path: row.get("path"), vibes, metadata },
+                score: row.get::<f64, _>("score") as f32,
+                matched_vibes,
+            }
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn database() -> Mp3Database {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        database
+    }
+
+    #[tokio::test]
+    async fn stored_metadata_shows_up_in_headers() {
+        let database = database().await;
+        let track_id = database.add_track("/music/01 - track.mp3").await.unwrap();
+
+        let track = database.get_track_header(track_id).await.unwrap().unwrap();
+        assert_eq!(track.metadata, Metadata::default());
+        assert_eq!(track.display_name(), "01 - track.mp3");
+
+        let metadata = Metadata {
+            title: Some("Clair de Lune".to_string()),
+            artist: Some("Debussy".to_string()),
+            album: None,
+            duration: Some(Duration::from_secs(300)),
+        };
+        database.set_track_metadata(track_id, &metadata).await.unwrap();
+        database.associate_vibe_with_track(track_id, "night").await.unwrap();
+
+        let track = database.get_track_header(track_id).await.unwrap().unwrap();
+        assert_eq!(track.metadata, metadata);
+        assert_eq!(track.display_name(), "Debussy - Clair de Lune");
+
+        let tracks = database.get_tracks_by_vibes(&["night"]).await.unwrap();
+        let track = tracks.iter().find(|track| track.id == track_id).unwrap();
+        assert_eq!(track.metadata, metadata);
+    }
+
+    #[tokio::test]
+    async fn mutations_publish_library_updates() {
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        let database = database().await.with_events(events);
+
+        let track_id = database.add_track("/music/Drizzle.mp3").await.unwrap();
+        database.associate_vibe_with_track(track_id, "rainy").await.unwrap();
+        database.get_all_tracks().await.unwrap();
+
+        assert!(matches!(receiver.try_recv(), Ok(VibingEvent::LibraryUpdated)));
+        assert!(matches!(receiver.try_recv(), Ok(VibingEvent::LibraryUpdated)));
+        assert!(receiver.try_recv().is_err());
+
+        database.begin_batch();
+        for i in 0..100 {
+            database.add_track(&format!("/music/{i}.mp3")).await.unwrap();
         }
+        database.end_batch();
+        assert!(matches!(receiver.try_recv(), Ok(VibingEvent::LibraryUpdated)));
+        assert!(receiver.try_recv().is_err());
 
-        Ok(tracks)
+        database.begin_batch();
+        database.end_batch();
+        assert!(receiver.try_recv().is_err());
     }
 
+    #[tokio::test]
+    async fn weather_feedback_matches_on_shared_vibes() {
+        let database = database().await;
+        let context = |weather: &str| Context::from_vibes("morning", "autumn", weather).unwrap();
+        database.record_feedback(1, Feedback::Skip, Some(context("rainy,windy,coldy"))).await.unwrap();
+        database.record_feedback(2, Feedback::Skip, Some(context("rainy"))).await.unwrap();
+        let vibes = |weather: &str| context(weather).vibe_names();
+
+        let skips = |tallies: Vec<FeedbackTally>| tallies.iter().map(|tally| (tally.track_id, tally.skips)).collect::<Vec<_>>();
+        let third = 2.0 / 3.0;
+        assert_eq!(skips(database.get_feedback_for(&vibes("rainy")).await.unwrap()), [(1, 1.0), (2, 1.0)]);
+        assert_eq!(skips(database.get_feedback_for(&vibes("rainy,coldy")).await.unwrap()), [(1, 1.0), (2, 1.0)]);
+        assert_eq!(skips(database.get_feedback_for(&vibes("windy")).await.unwrap()), [(1, 1.0), (2, third)]);
+        assert_eq!(skips(database.get_feedback_for(&vibes("sunny")).await.unwrap()), [(1, third), (2, third)]);
+    }
+
+    #[tokio::test]
+    async fn ranks_tracks_in_one_pass() {
+        let database = database().await;
+
+        let ranked = database.rank_tracks_by_vibes(&[("night", 2.0), ("evening", 0.5), ("morning", 1.0), ("night", 1.0), ("foggy", 9.0)]).await.unwrap();
+        let scores: Vec<_> = ranked.iter()
+            .map(|r| (r.track.display_name(), r.score, r.matched_vibes.clone()))
+            .collect();
+        assert_eq!(scores, [
+            ("TownNight.mp3".to_string(), 3.5, vec!["night".to_string(), "evening".to_string()]),
+            ("Glorious_morning.mp3".to_string(), 1.0, vec!["morning".to_string()]),
+            ("MorningRain.mp3".to_string(), 1.0, vec!["morning".to_string()]),
+        ]);
+
+        let mut vibes: Vec<_> = ranked[2].track.vibes.iter().map(|vibe| (vibe.name.as_str(), vibe.group_name.as_str())).collect();
+        vibes.sort();
+        assert_eq!(vibes, [("morning", "daytime"), ("rainy", "weather")]);
+
+        assert!(database.rank_tracks_by_vibes(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pages_through_tracks_with_their_vibes() {
+        let database = database().await;
+
+        let first = database.get_tracks_page(0, 4).await.unwrap();
+        let second = database.get_tracks_page(first.last().unwrap().id, 4).await.unwrap();
+        assert_eq!((first.len(), second.len()), (4, 2));
+
+        let paged: Vec<_> = first.into_iter().chain(second).collect();
+        let all = database.get_all_tracks().await.unwrap();
+        assert_eq!(paged, all);
+        for track in &all {
+            assert_eq!(track.vibes, database.get_vibes_for_track(track.id).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_groups_with_and_without_vibes() {
+        let database = database().await;
+        database.add_vibe_group("instrument").await.unwrap();
+
+        let groups = database.get_all_vibe_groups().await.unwrap();
+        let names: Vec<_> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, ["seasonal", "weather", "daytime", "mood", "event", "instrument"]);
+        assert_eq!(groups[0].vibes, database.get_vibes_in_group("seasonal").await.unwrap());
+        assert!(groups[5].vibes.is_empty());
+    }
+
+    async fn matching(database: &Mp3Database, query: VibeQuery) -> Vec<String> {
+        database.get_tracks_matching(&query).await.unwrap().iter().map(TrackHeader::display_name).collect()
+    }
+
+    #[tokio::test]
+    async fn compiles_vibe_queries_to_parameterized_sql() {
+        let database = database().await;
+        let injection = "x' OR 1 --";
+        let query = VibeQuery::all([
+            VibeQuery::any([VibeQuery::vibe("rainy"), VibeQuery::vibe(injection)]),
+            !VibeQuery::vibe("summer"),
+        ]);
+        let mut sql = String::new();
+        let mut params = Vec::new();
+        query.to_sql(&mut sql, &mut params);
+
+        // the names travel as bound parameters, never in the sql text
+        assert_eq!(params, ["rainy", injection, "summer"]);
+        assert!(params.iter().all(|param| !sql.contains(param)));
+
+        assert_eq!(matching(&database, query).await, ["MorningRain.mp3", "Rain.mp3"]);
+        assert!(matching(&database, VibeQuery::vibe(injection)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn matches_any_all_and_not() {
+        let database = database().await;
+
+        assert_eq!(
+            matching(&database, VibeQuery::any([VibeQuery::vibe("rainy"), VibeQuery::vibe("sunny")])).await,
+            ["MorningRain.mp3", "Rain.mp3", "Summertime.mp3"]
+        );
+        assert_eq!(
+            matching(&database, VibeQuery::all([VibeQuery::vibe("morning"), !VibeQuery::vibe("rainy")])).await,
+            ["Glorious_morning.mp3"]
+        );
+        assert_eq!(
+            matching(&database, VibeQuery::all([
+                VibeQuery::any([VibeQuery::vibe("rainy"), VibeQuery::vibe("cloudy")]),
+                VibeQuery::vibe("morning"),
+            ])).await,
+            ["MorningRain.mp3"]
+        );
+        assert_eq!(
+            matching(&database, !VibeQuery::any([VibeQuery::vibe("morning"), VibeQuery::vibe("summer"), VibeQuery::vibe("rainy")])).await,
+            ["TownNight.mp3"]
+        );
+        assert_eq!(matching(&database, VibeQuery::all([])).await.len(), 6);
+        assert!(matching(&database, VibeQuery::any([])).await.is_empty());
+        assert!(matching(&database, VibeQuery::vibe("x' OR 1 --")).await.is_empty());
+    }
 }
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use crate::configuration::ConfigError;
+
+#[derive(Debug)]
+pub enum VibingError {
+    Io(std::io::Error),
+    Stream(rodio::StreamError),
+    Decode(rodio::decoder::DecoderError),
+    Tag(audiotags::Error),
+    Http(reqwest::Error),
+    Parse(String),
+    Sqlx(sqlx::Error),
+    Migrate(sqlx::migrate::MigrateError),
+    Config(ConfigError),
+    MissingVibes(Vec<String>),
+}
+
+impl fmt::Display for VibingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VibingError::Io(e) => write!(f, "i/o error: {e}"),
+            VibingError::Stream(e) => write!(f, "cannot open audio stream: {e}"),
+            VibingError::Decode(e) => write!(f, "cannot decode audio: {e}"),
+            VibingError::Tag(e) => write!(f, "cannot read tags: {e}"),
+            VibingError::Http(e) => write!(f, "http error: {e}"),
+            VibingError::Parse(reason) => write!(f, "parse error: {reason}"),
+            VibingError::Sqlx(e) => write!(f, "database error: {e}"),
+            VibingError::Migrate(e) => write!(f, "migration error: {e}"),
+            VibingError::Config(e) => write!(f, "{e}"),
+            VibingError::MissingVibes(names) => write!(f, "vibes missing from the vibes table: {}", names.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for VibingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VibingError::Io(e) => Some(e),
+            VibingError::Stream(e) => Some(e),
+            VibingError::Decode(e) => Some(e),
+            VibingError::Tag(e) => Some(e),
+            VibingError::Http(e) => Some(e),
+            VibingError::Parse(_) => None,
+            VibingError::Sqlx(e) => Some(e),
+            VibingError::Migrate(e) => Some(e),
+            VibingError::Config(e) => Some(e),
+            VibingError::MissingVibes(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VibingError {
+    fn from(e: std::io::Error) -> Self {
+        VibingError::Io(e)
+    }
+}
+
+impl From<rodio::StreamError> for VibingError {
+    fn from(e: rodio::StreamError) -> Self {
+        VibingError::Stream(e)
+    }
+}
+
+impl From<rodio::decoder::DecoderError> for VibingError {
+    fn from(e: rodio::decoder::DecoderError) -> Self {
+        VibingError::Decode(e)
+    }
+}
+
+impl From<audiotags::Error> for VibingError {
+    fn from(e: audiotags::Error) -> Self {
+        VibingError::Tag(e)
+    }
+}
+
+impl From<reqwest::Error> for VibingError {
+    fn from(e: reqwest::Error) -> Self {
+        VibingError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for VibingError {
+    fn from(e: serde_json::Error) -> Self {
+        VibingError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseFloatError> for VibingError {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        VibingError::Parse(e.to_string())
+    }
+}
+
+impl From<sqlx::Error> for VibingError {
+    fn from(e: sqlx::Error) -> Self {
+        VibingError::Sqlx(e)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for VibingError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        VibingError::Migrate(e)
+    }
+}
+
+impl From<ConfigError> for VibingError {
+    fn from(e: ConfigError) -> Self {
+        VibingError::Config(e)
+    }
+}
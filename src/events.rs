@@ -0,0 +1,48 @@
+use tokio::sync::broadcast;
+
+use std::time::Duration;
+
+use crate::{data_collector::{Season, TimePeriod, WeatherSet}, database::{PlayOutcome, TrackHeader}};
+
+const CAPACITY: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum VibingEvent {
+    TrackStarted(TrackHeader),
+    TrackEnded { track: TrackHeader, listened: Duration, outcome: PlayOutcome },
+    Paused,
+    // the player let go of its tracks, play() starts them over
+    Stopped,
+    Resumed,
+    VolumeChanged(f32),
+    ContextChanged { time: TimePeriod, season: Season, weather: WeatherSet },
+    LibraryUpdated,
+}
+
+// cheap to clone, every clone publishes to the same subscribers
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<VibingEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<VibingEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: VibingEvent) {
+        // nobody listening is fine
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(CAPACITY)
+    }
+}
@@ -1,5 +1,14 @@
-
+pub mod configuration;
 pub mod database;
+pub mod error;
+pub mod events;
+pub mod library_scanner;
+pub mod play_history;
 pub mod data_collector;
+pub mod context_sources;
 pub mod audio_services;
-pub mod audio_recommender;
\ No newline at end of file
+pub mod audio_recommender;
+pub mod scheduler;
+pub mod solar;
+pub mod weather_mapping;
+pub mod weather_providers;
\ No newline at end of file
@@ -0,0 +1,215 @@
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, sync::Arc};
+
+use tokio::sync::RwLock;
+
+use crate::{database::Mp3Database, error::VibingError};
+
+// formats the default rodio decoders handle
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "flac", "ogg", "wav"];
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub added: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, VibingError)>,
+}
+
+pub struct LibraryScanner {
+    database: Arc<RwLock<Mp3Database>>,
+}
+
+impl LibraryScanner {
+    pub fn new(database: Arc<RwLock<Mp3Database>>) -> Self {
+        Self { database }
+    }
+
+    pub fn is_audio_file(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| AUDIO_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+    }
+
+    // the extension only says what a file claims to be, rodio reading its header says it will play
+    pub fn probe(path: &Path) -> Result<(), VibingError> {
+        rodio::Decoder::try_from(fs::File::open(path)?)?;
+        Ok(())
+    }
+
+    // safe to re-run, files already in track_pointers are reported as skipped
+    pub async fn scan(&self, directories: &[impl AsRef<Path>]) -> ScanReport {
+        let mut report = ScanReport::default();
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+
+        for directory in directories {
+            Self::collect_files(directory.as_ref(), &mut files, &mut report, &mut visited);
+        }
+        files.sort();
+
+        // one LibraryUpdated for the whole run
+        let database = self.database.read().await;
+        database.begin_batch();
+        for file in files {
+            let Some(path) = file.to_str() else {
+                report.failed.push((file.clone(), VibingError::Parse(format!("path is not valid utf-8: {}", file.display()))));
+                continue;
+            };
+            if let Err(e) = Self::probe(&file) {
+                report.failed.push((file, e));
+                continue;
+            }
+
+            match database.add_track(path).await {
+                Ok(track_id) => {
+                    // untagged files are common, they just show up under their file name
+                    database.refresh_metadata(track_id).await.ok();
+                    report.added.push(file);
+                }
+                Err(VibingError::Sqlx(sqlx::Error::Database(e))) if e.is_unique_violation() => report.skipped.push(file),
+                Err(e) => report.failed.push((file, e)),
+            }
+        }
+        database.end_batch();
+
+        report
+    }
+
+    // visited holds canonical directories, a symlink back up the tree is only walked once
+    fn collect_files(directory: &Path, files: &mut Vec<PathBuf>, report: &mut ScanReport, visited: &mut HashSet<PathBuf>) {
+        // absolute paths so the same file is recognised whatever directory we were started from
+        let directory = match directory.canonicalize() {
+            Ok(directory) => directory,
+            Err(e) => {
+                report.failed.push((directory.to_path_buf(), e.into()));
+                return;
+            }
+        };
+        if !visited.insert(directory.clone()) {
+            return;
+        }
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.failed.push((directory, e.into()));
+                return;
+            }
+        };
+
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    report.failed.push((directory.clone(), e.into()));
+                    continue;
+                }
+            };
+
+            if path.is_dir() {
+                Self::collect_files(&path, files, report, visited);
+            } else if Self::is_audio_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventBus, VibingEvent};
+
+    // a tenth of a second of 8kHz mono silence, rodio tells the format from the content
+    fn wav() -> Vec<u8> {
+        let samples = [0u8; 1600];
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + samples.len() as u32).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend([1u16, 1].map(u16::to_le_bytes).concat());
+        wav.extend([8000u32, 16000].map(u32::to_le_bytes).concat());
+        wav.extend([2u16, 16].map(u16::to_le_bytes).concat());
+        wav.extend(b"data");
+        wav.extend((samples.len() as u32).to_le_bytes());
+        wav.extend(samples);
+        wav
+    }
+
+    async fn scanner() -> LibraryScanner {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        LibraryScanner::new(Arc::new(RwLock::new(database)))
+    }
+
+    #[tokio::test]
+    async fn rescanning_only_adds_new_files() {
+        let library = tempfile::tempdir().unwrap();
+        fs::create_dir_all(library.path().join("rain/night")).unwrap();
+        fs::write(library.path().join("Ocean.MP3"), wav()).unwrap();
+        fs::write(library.path().join("rain/Drizzle.flac"), wav()).unwrap();
+        fs::write(library.path().join("rain/night/Storm.ogg"), wav()).unwrap();
+        fs::write(library.path().join("rain/cover.jpg"), b"").unwrap();
+
+        let scanner = scanner().await;
+
+        let report = scanner.scan(&[library.path()]).await;
+        assert_eq!(report.added.len(), 3);
+        assert!(report.skipped.is_empty() && report.failed.is_empty());
+
+        fs::write(library.path().join("rain/Thunder.wav"), wav()).unwrap();
+        let report = scanner.scan(&[library.path()]).await;
+        assert_eq!(report.added, [library.path().canonicalize().unwrap().join("rain/Thunder.wav")]);
+        assert_eq!(report.skipped.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn publishes_one_library_update_per_scan() {
+        let library = tempfile::tempdir().unwrap();
+        for name in ["a.wav", "b.wav", "c.wav"] {
+            fs::write(library.path().join(name), wav()).unwrap();
+        }
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap().with_events(events);
+        database.migrate().await.unwrap();
+        let scanner = LibraryScanner::new(Arc::new(RwLock::new(database)));
+
+        assert_eq!(scanner.scan(&[library.path()]).await.added.len(), 3);
+        assert!(matches!(receiver.try_recv(), Ok(VibingEvent::LibraryUpdated)));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_files_rodio_cannot_decode() {
+        let library = tempfile::tempdir().unwrap();
+        fs::write(library.path().join("Ocean.mp3"), wav()).unwrap();
+        fs::write(library.path().join("Truncated.mp3"), b"").unwrap();
+
+        let report = scanner().await.scan(&[library.path()]).await;
+        assert_eq!(report.added.len(), 1);
+        assert!(matches!(&report.failed[..], [(path, VibingError::Decode(_))] if path.ends_with("Truncated.mp3")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn walks_a_symlink_loop_once() {
+        let library = tempfile::tempdir().unwrap();
+        fs::create_dir_all(library.path().join("rain")).unwrap();
+        fs::write(library.path().join("rain/Drizzle.flac"), wav()).unwrap();
+        std::os::unix::fs::symlink("..", library.path().join("rain/loop")).unwrap();
+
+        let report = scanner().await.scan(&[library.path()]).await;
+        assert_eq!(report.added, [library.path().canonicalize().unwrap().join("rain/Drizzle.flac")]);
+        assert!(report.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_missing_directories() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        let scanner = LibraryScanner::new(Arc::new(RwLock::new(database)));
+
+        let report = scanner.scan(&["/definitely/not/a/music/folder"]).await;
+        assert!(report.added.is_empty());
+        assert!(matches!(report.failed[..], [(_, VibingError::Io(_))]));
+    }
+}
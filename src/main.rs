@@ -1,45 +1,207 @@
 use std::{sync::Arc, time::Duration};
 
-use tokio::{sync::RwLock, time::sleep};
-use vibing::{audio_recommender::Recommender, audio_services::Audio, data_collector::{TimeData, WeatherData}, database::Mp3Database};
+use tokio::sync::{broadcast::error::RecvError, RwLock};
+use vibing::{audio_recommender::Recommender, audio_services::Fades, configuration::Config, data_collector::{Context, TimeData}, database::{Feedback, Mp3Database}, error::VibingError, events::{EventBus, VibingEvent}, library_scanner::LibraryScanner, scheduler::Scheduler, weather_providers};
 
 #[tokio::main]
 async fn main() {
-    // let database_url = std::env::var("DATABASE_URL")
-    //         .expect("DATABASE_URL is not existed in .env");
+    let config = match Config::load("app_config.json") {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
 
-    let database = Arc::new(
-        RwLock::new(
-            Mp3Database::new("sqlite://vibing_library.sqlite").await.expect("db error")
-        )
-    );
+    let events = EventBus::default();
+
+    let database = match Mp3Database::new(&config.database_url).await {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    // an existing library picks up the tables and vibes added since it was created
+    if let Err(e) = database.migrate().await {
+        eprintln!("cannot migrate {}: {e}", config.database_url);
+        std::process::exit(1);
+    }
+    let database = Arc::new(RwLock::new(database.with_events(events.clone())));
+
+    // vibing scan <dir>... imports a music folder into the library
+    // vibing list prints the library
+    // vibing stats prints what has been listened to
+    // vibing like|dislike [--now | --context <time> <season> <weather>] <track id>... teaches the recommender,
+    //   in every context unless one is given, --now uses the current one
+    // vibing plan [hours] prints what would play over the next hours, 6 by default
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        Some((command, directories)) if command == "scan" => {
+            let report = LibraryScanner::new(database).scan(directories).await;
+            for (path, e) in &report.failed {
+                eprintln!("failed {}: {e}", path.display());
+            }
+            println!("added: {}, skipped: {}, failed: {}", report.added.len(), report.skipped.len(), report.failed.len());
+            return;
+        }
+        Some((command, _)) if command == "list" => {
+            let database = database.read().await;
+            let mut after_id = 0;
+            loop {
+                let tracks = match database.get_tracks_page(after_id, 500).await {
+                    Ok(tracks) => tracks,
+                    Err(e) => {
+                        eprintln!("{e}");
+                        break;
+                    }
+                };
+                let Some(last) = tracks.last() else {
+                    break;
+                };
+                after_id = last.id;
+
+                for track in tracks {
+                    let vibes: Vec<_> = track.vibes.iter().map(|vibe| vibe.name.as_str()).collect();
+                    println!("{:>5}  {}  [{}]", track.id, track.display_name(), vibes.join(", "));
+                }
+            }
+            return;
+        }
+        Some((command, args)) if command == "like" || command == "dislike" => {
+            let feedback = Feedback::from_name(command).unwrap();
+            let (context, track_ids) = match args {
+                [flag, track_ids @ ..] if flag == "--now" => (Some(current_context(&config, database.clone()).await), track_ids),
+                [flag, time, season, weather, track_ids @ ..] if flag == "--context" => {
+                    let context = Context::from_vibes(time, season, weather)
+                        .ok_or_else(|| VibingError::Parse(format!("not a context: {time} {season} {weather}")));
+                    (Some(context), track_ids)
+                }
+                [flag, ..] if flag == "--context" => {
+                    eprintln!("--context takes a time period, a season and a weather, e.g. night winter rainy,coldy");
+                    std::process::exit(1);
+                }
+                track_ids => (None, track_ids),
+            };
+            let context = match context.transpose() {
+                Ok(context) => context,
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            };
+
+            let database = database.read().await;
+            for track_id in track_ids {
+                let result = match track_id.parse() {
+                    Ok(track_id) => database.record_feedback(track_id, feedback, context).await.map(|_| ()),
+                    Err(_) => Err(VibingError::Parse(format!("not a track id: {track_id}"))),
+                };
+                if let Err(e) = result {
+                    eprintln!("{e}");
+                }
+            }
+            return;
+        }
+        Some((command, _)) if command == "stats" => {
+            let database = database.read().await;
+            let stats = async {
+                println!("most played:");
+                for (track, plays) in database.get_most_played(10).await? {
+                    println!("{plays:>5}  {}", track.display_name());
+                }
+                println!("most skipped:");
+                for (track, skips) in database.get_most_skipped(10).await? {
+                    println!("{skips:>5}  {}", track.display_name());
+                }
+                println!("listening time by vibe:");
+                for (vibe, listened) in database.get_listening_time_by_vibe().await? {
+                    println!("{:>5}m  {vibe}", listened.as_secs() / 60);
+                }
+                Ok::<_, VibingError>(())
+            };
+            if let Err(e) = stats.await {
+                eprintln!("{e}");
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let weather_provider = match weather_providers::from_config(&config.weather, database.clone()) {
+        Ok(weather_provider) => weather_provider,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
 
     let recommender = Arc::new(
         RwLock::new(
-            Recommender::new(database)
+            Recommender::new(
+                database,
+                TimeData::new(config.time_periods.clone()),
+                weather_provider,
+            ).with_weights(config.recommender.clone())
         )
     );
 
-    let weather = WeatherData::get_weather().await;
-    println!("{:?}", weather);
-
-    let time = TimeData::get_time();
-    println!("{:?}", time);
-
-    let season = TimeData::get_season();
-    println!("{:?}", season);
+    // a vibe the recommender asks for but the table lacks would silently never match
+    if let Err(e) = recommender.read().await.check_vibes().await {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 
-    let tracks = recommender.read().await.get_track().await;
+    if let Some((command, hours)) = args.split_first() && command == "plan" {
+        let hours = hours.first().and_then(|hours| hours.parse().ok()).unwrap_or(6);
+        match recommender.read().await.get_timeline(chrono::Local::now(), hours).await {
+            Ok(timeline) => {
+                for slot in timeline {
+                    let context = slot.context;
+                    println!("{}  {} {} {}", slot.start.format("%a %H:%M"), context.time.vibe_name(), context.season.vibe_name(), context.weather.vibe_name());
+                    for track in slot.tracks {
+                        println!("       {}", track.display_name());
+                    }
+                }
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+        return;
+    }
 
-    for track in tracks {
-        println!("now play: {:?} - {:?}", track.path, track.vibes);
-        let mut audio = Audio::new(&track.path);
-        audio.set_volume(0.2);
-        audio.play();
+    let mut scheduler = Scheduler::new(recommender, Duration::from_secs(config.scheduler.interval_secs))
+        .with_events(events.clone())
+        .with_lookahead(Duration::from_secs(config.scheduler.lookahead_secs));
 
-        while !audio.is_end() {
-            sleep(Duration::from_secs(1)).await;
+    let mut receiver = events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(VibingEvent::TrackStarted(track)) => {
+                    let vibes: Vec<_> = track.vibes.iter().map(|vibe| vibe.name.as_str()).collect();
+                    println!("now play: {} [{}]", track.display_name(), vibes.join(", "));
+                }
+                Ok(VibingEvent::ContextChanged { time, season, weather }) => {
+                    println!("context changed: {time:?} {season:?} {weather:?}");
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
         }
+    });
+
+    if let Err(e) = scheduler.refresh().await {
+        eprintln!("{e}");
     }
+    println!("{:?} ({:?})", scheduler.get_context(), scheduler.get_fallback_level());
+
+    scheduler.run(config.player.volume, Fades::from(&config.player)).await;
+}
+
+// what the player would go by right now
+async fn current_context(config: &Config, database: Arc<RwLock<Mp3Database>>) -> Result<Context, VibingError> {
+    let time_data = TimeData::new(config.time_periods.clone());
+    let weather = weather_providers::from_config(&config.weather, database)?.get_weather().await?;
 
+    Ok(Context { time: time_data.get_time(), season: time_data.get_season(), weather })
 }
\ No newline at end of file
@@ -0,0 +1,142 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local};
+use tokio::sync::{broadcast::{error::RecvError, Receiver}, RwLock};
+
+use crate::{data_collector::Context, database::{Feedback, Mp3Database, PlayOutcome, PlayRecord, TrackHeader}, error::VibingError, events::{EventBus, VibingEvent}};
+
+// follows the player through the event bus and writes a play_history row for every track that ends
+pub struct PlayHistoryRecorder {
+    database: Arc<RwLock<Mp3Database>>,
+    receiver: Receiver<VibingEvent>,
+    context: Option<Context>,
+    // start time of the tracks currently playing, two of them during a crossfade
+    started: HashMap<i64, (DateTime<Local>, Option<Context>)>,
+}
+
+impl PlayHistoryRecorder {
+    pub fn new(database: Arc<RwLock<Mp3Database>>, events: &EventBus, context: Option<Context>) -> Self {
+        Self {
+            database,
+            receiver: events.subscribe(),
+            context,
+            started: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if let Err(e) = self.handle(event).await {
+                        eprintln!("cannot record play: {e}");
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => eprintln!("play history missed {missed} events"),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    pub async fn handle(&mut self, event: VibingEvent) -> Result<(), VibingError> {
+        match event {
+            VibingEvent::ContextChanged { time, season, weather } => {
+                self.context = Some(Context { time, season, weather });
+            }
+            VibingEvent::TrackStarted(track) => {
+                self.started.insert(track.id, (Local::now(), self.context));
+            }
+            VibingEvent::TrackEnded { track, listened, outcome } => {
+                self.record(&track, listened, outcome).await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn record(&mut self, track: &TrackHeader, listened: Duration, outcome: PlayOutcome) -> Result<(), VibingError> {
+        // a track that ended without a start we saw still counts, from when it ended
+        let (started_at, context) = self.started.remove(&track.id).unwrap_or((Local::now(), self.context));
+
+        let play = PlayRecord {
+            track_id: track.id,
+            started_at,
+            listened,
+            outcome,
+            time: context.map(|context| context.time.vibe_name().to_string()),
+            season: context.map(|context| context.season.vibe_name().to_string()),
+            weather: context.map(|context| context.weather.vibe_name()),
+        };
+        let database = self.database.read().await;
+        database.record_play(&play).await?;
+        // skipping is the feedback the player can give without asking
+        if outcome == PlayOutcome::Skipped {
+            database.record_feedback(track.id, Feedback::Skip, context).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_collector::{Season, TimePeriod, Weather};
+
+    #[tokio::test]
+    async fn records_plays_with_their_context() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let database = Arc::new(RwLock::new(database));
+        let events = EventBus::default();
+        let mut recorder = PlayHistoryRecorder::new(database.clone(), &events, None);
+
+        let tracks = database.read().await.get_all_tracks().await.unwrap();
+        let track = |name: &str| tracks.iter().find(|track| track.path.ends_with(name)).unwrap().clone();
+        let (rain, town, ocean) = (track("/Rain.mp3"), track("/TownNight.mp3"), track("/Ocean.mp3"));
+        let context_changed = VibingEvent::ContextChanged { time: TimePeriod::Night(23.0), season: Season::Winter(12), weather: Weather::Rainy(12.0).into() };
+
+        for event in [
+            VibingEvent::TrackStarted(rain.clone()),
+            context_changed,
+            VibingEvent::TrackStarted(town.clone()),
+            VibingEvent::TrackEnded { track: rain.clone(), listened: Duration::from_secs(200), outcome: PlayOutcome::Completed },
+            VibingEvent::TrackEnded { track: town.clone(), listened: Duration::from_secs(15), outcome: PlayOutcome::Skipped },
+            VibingEvent::TrackStarted(rain.clone()),
+            VibingEvent::TrackEnded { track: rain.clone(), listened: Duration::from_secs(100), outcome: PlayOutcome::Completed },
+            VibingEvent::TrackStarted(ocean.clone()),
+            VibingEvent::TrackEnded { track: ocean.clone(), listened: Duration::from_secs(10), outcome: PlayOutcome::Rewound },
+        ] {
+            recorder.handle(event).await.unwrap();
+        }
+
+        let database = database.read().await;
+        let plays = database.get_recent_plays(10).await.unwrap();
+        assert_eq!(plays.len(), 4);
+        let town_play = plays.iter().find(|play| play.track_id == town.id).unwrap();
+        assert_eq!(town_play.outcome, PlayOutcome::Skipped);
+        assert_eq!(
+            (town_play.time.as_deref(), town_play.season.as_deref(), town_play.weather.as_deref()),
+            (Some("night"), Some("winter"), Some("rainy"))
+        );
+        let first_rain = plays.iter().find(|play| play.listened == Duration::from_secs(200)).unwrap();
+        assert_eq!(first_rain.time, None);
+
+        let most_played = database.get_most_played(1).await.unwrap();
+        assert_eq!((most_played[0].0.id, most_played[0].1), (rain.id, 2));
+        let most_skipped = database.get_most_skipped(10).await.unwrap();
+        assert_eq!(most_skipped.iter().map(|(track, skips)| (track.id, *skips)).collect::<Vec<_>>(), [(town.id, 1)]);
+        let context = Context { time: TimePeriod::Night(1.0), season: Season::Winter(1), weather: Weather::Rainy(10.0).into() };
+        let feedback = database.get_feedback_for(&context.vibe_names()).await.unwrap();
+        assert_eq!(feedback.iter().map(|tally| (tally.track_id, tally.skips)).collect::<Vec<_>>(), [(town.id, 1.0)]);
+
+        let listened = database.get_listening_time_by_vibe().await.unwrap();
+        assert_eq!(listened, [
+            ("rainy".to_string(), Duration::from_secs(300)),
+            ("evening".to_string(), Duration::from_secs(15)),
+            ("night".to_string(), Duration::from_secs(15)),
+            ("summer".to_string(), Duration::from_secs(10)),
+        ]);
+    }
+}
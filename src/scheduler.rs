@@ -0,0 +1,419 @@
+use std::{collections::VecDeque, sync::{Arc, Mutex}, time::Duration};
+
+use chrono::{DateTime, Local};
+use tokio::{sync::RwLock, time::{sleep, Instant}};
+
+use crate::{audio_recommender::{FallbackLevel, Recommender}, audio_services::{Fades, Player, Transition}, context_sources::Moment, data_collector::{Context, ContextTracker, ForecastHour, WeatherSet}, database::TrackHeader, error::VibingError, events::EventBus, play_history::PlayHistoryRecorder};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+// a clock that only moves when told to, for driving the scheduler in tests
+pub struct ManualClock {
+    now: Mutex<DateTime<Local>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Local>) -> Self {
+        Self { now: Mutex::new(start) }
+    }
+
+    pub fn set(&self, time: DateTime<Local>) {
+        *self.now.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.lock().unwrap()
+    }
+}
+
+pub struct Scheduler {
+    recommender: Arc<RwLock<Recommender>>,
+    clock: Arc<dyn Clock>,
+    interval: Duration,
+    tracker: ContextTracker,
+    events: EventBus,
+    queue: VecDeque<TrackHeader>,
+    fallback_level: Option<FallbackLevel>,
+    forecast: Vec<ForecastHour>,
+    lookahead: Duration,
+    // the forecast weather the queue leans toward, if any
+    anticipated: Option<WeatherSet>,
+}
+
+impl Scheduler {
+    pub fn new(recommender: Arc<RwLock<Recommender>>, interval: Duration) -> Self {
+        Self::with_clock(recommender, interval, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(recommender: Arc<RwLock<Recommender>>, interval: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            recommender,
+            clock,
+            interval,
+            tracker: ContextTracker::new(EventBus::default()),
+            events: EventBus::default(),
+            queue: VecDeque::new(),
+            fallback_level: None,
+            forecast: Vec::new(),
+            lookahead: Duration::from_secs(30 * 60),
+            anticipated: None,
+        }
+    }
+
+    pub fn with_lookahead(mut self, lookahead: Duration) -> Self {
+        self.lookahead = lookahead;
+        self
+    }
+
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.tracker = ContextTracker::new(events.clone());
+        self.events = events;
+        self
+    }
+
+    pub fn get_context(&self) -> Option<Context> {
+        self.tracker.get_context()
+    }
+
+    pub fn get_queue(&self) -> &VecDeque<TrackHeader> {
+        &self.queue
+    }
+
+    // how the current queue was picked, None until the first recommendation
+    pub fn get_fallback_level(&self) -> Option<FallbackLevel> {
+        self.fallback_level
+    }
+
+    pub async fn context_at(&self, weather: impl Into<WeatherSet>) -> Context {
+        let now = self.clock.now();
+        let recommender = self.recommender.read().await;
+        Context {
+            time: recommender.get_time_data().get_time_at(now),
+            season: recommender.get_time_data().get_season_at(now),
+            weather: weather.into(),
+        }
+    }
+
+    // re-evaluate the context, returns true if the queue has been replaced for a new context.
+    // a forecast change coming up only swaps the queue, the playing track carries on
+    pub async fn refresh(&mut self) -> Result<bool, VibingError> {
+        let recommender = self.recommender.read().await;
+        let outlook = recommender.get_weather_provider().get_weather_and_forecast().await;
+        drop(recommender);
+
+        let (weather, forecast) = match outlook {
+            Ok(outlook) => outlook,
+            // a flaky weather response shouldn't stop the music, keep the last known weather.
+            // the old forecast goes, it would only keep pointing at weather that may not come
+            Err(e) => {
+                eprintln!("cannot get weather: {e}");
+                (self.get_context().map(|context| context.weather).unwrap_or(WeatherSet::new(0.0)), Vec::new())
+            }
+        };
+        self.forecast = forecast;
+
+        let context = self.context_at(weather).await;
+        let changed = self.update_context(context).await?;
+        // while a forecast change is in view its share grows every refresh, so is the queue's lean
+        // toward it. once it's gone the queue goes back to the context alone
+        let upcoming = self.upcoming_weather(&context);
+        if !changed && (upcoming.is_some() || self.anticipated.is_some()) {
+            self.queue = self.recommend().await?;
+        }
+
+        Ok(changed)
+    }
+
+    // the first forecast weather unlike the context's within the lookahead, with its share of the
+    // queue growing from 0 when it comes into view to 1 when it starts
+    fn upcoming_weather(&self, context: &Context) -> Option<(WeatherSet, f32)> {
+        let now = self.clock.now();
+        self.forecast.iter()
+            .filter(|hour| hour.start > now)
+            .take_while(|hour| (hour.start - now).to_std().is_ok_and(|until| until <= self.lookahead))
+            .find(|hour| !hour.weather.same_vibes(&context.weather))
+            .map(|hour| {
+                let until = (hour.start - now).to_std().unwrap_or_default();
+                (hour.weather, 1.0 - until.as_secs_f32() / self.lookahead.as_secs_f32())
+            })
+    }
+
+    pub async fn update_context(&mut self, context: Context) -> Result<bool, VibingError> {
+        let changed = self.tracker.update(context);
+
+        if changed {
+            self.queue = self.recommend().await?;
+        }
+
+        Ok(changed)
+    }
+
+    // called at a track boundary, refills the queue for the same context once it runs dry
+    pub async fn next_track(&mut self) -> Result<Option<TrackHeader>, VibingError> {
+        if self.queue.is_empty() {
+            self.queue = self.recommend().await?;
+        }
+
+        Ok(self.queue.pop_front())
+    }
+
+    pub async fn run(&mut self, volume: f32, fades: Fades) {
+        if self.get_context().is_none()
+            && let Err(e) = self.refresh().await
+        {
+            eprintln!("cannot refresh recommendations: {e}");
+        }
+
+        // every track the player finishes, skips or stops ends up in play_history
+        let database = self.recommender.read().await.get_database();
+        tokio::spawn(PlayHistoryRecorder::new(database, &self.events, self.get_context()).run());
+
+        let mut player = loop {
+            match Player::new(fades).map(|player| player.with_events(self.events.clone())) {
+                Ok(player) => break player,
+                Err(e) => {
+                    eprintln!("{e}, retrying");
+                    sleep(Duration::from_secs(5)).await;
+                }
+            }
+        };
+        player.set_volume(volume);
+        player.play();
+
+        let mut last_refresh = Instant::now();
+
+        loop {
+            if last_refresh.elapsed() >= self.interval {
+                match self.refresh().await {
+                    Ok(true) => {
+                        // whatever is waiting in the player was picked for the old context
+                        player.clear();
+                        player.set_next_transition(Transition::ContextChange);
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("cannot refresh recommendations: {e}"),
+                }
+                last_refresh = Instant::now();
+            }
+
+            // keep one track waiting in the player so it can be appended ahead of the boundary
+            if player.get_queue().is_empty() {
+                match self.next_track().await {
+                    Ok(Some(track)) => player.enqueue(track),
+                    Ok(None) => {}
+                    Err(e) => eprintln!("cannot get next track: {e}"),
+                }
+            }
+
+            player.update();
+
+            sleep(Duration::from_millis(250)).await;
+        }
+    }
+
+    async fn recommend(&mut self) -> Result<VecDeque<TrackHeader>, VibingError> {
+        let Some(context) = self.get_context() else {
+            return Ok(VecDeque::new());
+        };
+
+        // the sources work out time and season from the clock, the weather is the context's
+        let upcoming = self.upcoming_weather(&context);
+        let moment = Moment { weather: Some(context.weather), upcoming, ..Moment::new(self.clock.now()) };
+        let recommender = self.recommender.read().await;
+        let recommendations = recommender.get_recommendations_at(&moment).await?;
+        drop(recommender);
+        self.anticipated = upcoming.map(|(weather, _)| weather);
+        if recommendations.level >= FallbackLevel::Adjacent {
+            eprintln!("nothing matches {context:?}, falling back to {:?}", recommendations.level);
+        }
+        self.fallback_level = Some(recommendations.level);
+
+        Ok(recommendations.into_tracks().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{data_collector::{Season, TimeData, TimePeriod, Weather}, database::Mp3Database, events::VibingEvent, weather_providers::{FileWeatherProvider, FixedWeather, WeatherProvider}};
+
+    async fn scheduler_at(start: DateTime<Local>) -> (Scheduler, Arc<ManualClock>) {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let recommender = Recommender::new(Arc::new(RwLock::new(database)), TimeData::default(), Arc::new(FixedWeather::new(Weather::Default(20.0))));
+        let recommender = Arc::new(RwLock::new(recommender));
+        let clock = Arc::new(ManualClock::new(start));
+        let scheduler = Scheduler::with_clock(recommender, Duration::from_secs(60), clock.clone());
+        (scheduler, clock)
+    }
+
+    fn paths(scheduler: &Scheduler) -> Vec<&str> {
+        scheduler.get_queue().iter().map(|track| track.path.rsplit('/').next().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn switches_queue_when_time_period_changes() {
+        let start = Local.with_ymd_and_hms(2025, 6, 1, 6, 0, 0).unwrap();
+        let (mut scheduler, clock) = scheduler_at(start).await;
+
+        let context = scheduler.context_at(Weather::Rainy(25.0)).await;
+        assert!(scheduler.update_context(context).await.unwrap());
+        assert!(matches!(scheduler.get_context().unwrap().time, TimePeriod::Dawn(_)));
+        assert!(!paths(&scheduler).contains(&"Glorious_morning.mp3"));
+
+        clock.advance(chrono::Duration::minutes(30));
+        let context = scheduler.context_at(Weather::Rainy(26.0)).await;
+        assert!(!scheduler.update_context(context).await.unwrap());
+
+        clock.advance(chrono::Duration::hours(2));
+        let context = scheduler.context_at(Weather::Rainy(27.0)).await;
+        assert!(scheduler.update_context(context).await.unwrap());
+        assert!(matches!(scheduler.get_context().unwrap().time, TimePeriod::Morning(_)));
+        assert_eq!(paths(&scheduler)[0], "MorningRain.mp3");
+        assert!(paths(&scheduler).contains(&"Glorious_morning.mp3"));
+    }
+
+    #[tokio::test]
+    async fn publishes_context_changes_only() {
+        let start = Local.with_ymd_and_hms(2025, 12, 1, 23, 0, 0).unwrap();
+        let (scheduler, clock) = scheduler_at(start).await;
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        let mut scheduler = scheduler.with_events(events);
+
+        let context = scheduler.context_at(Weather::Coldy(8.0)).await;
+        scheduler.update_context(context).await.unwrap();
+        clock.advance(chrono::Duration::minutes(10));
+        let context = scheduler.context_at(Weather::Coldy(7.0)).await;
+        scheduler.update_context(context).await.unwrap();
+
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            VibingEvent::ContextChanged { time: TimePeriod::Night(_), season: Season::Winter(12), weather } if weather.contains(Weather::Coldy(0.0))
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn refills_queue_for_the_same_context() {
+        let start = Local.with_ymd_and_hms(2025, 6, 1, 21, 0, 0).unwrap();
+        let (mut scheduler, _clock) = scheduler_at(start).await;
+
+        let context = scheduler.context_at(Weather::Default(20.0)).await;
+        scheduler.update_context(context).await.unwrap();
+        let queued = scheduler.get_queue().len();
+        assert!(queued > 0);
+
+        for _ in 0..queued {
+            scheduler.next_track().await.unwrap().unwrap();
+        }
+        assert!(scheduler.next_track().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn leans_toward_the_forecast_weather_ahead_of_time() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecast/wttr_rain_at_noon.json");
+        let provider = FileWeatherProvider::new(path).unwrap();
+        let recommender = Recommender::new(Arc::new(RwLock::new(database)), TimeData::default(), Arc::new(provider));
+        let clock = Arc::new(ManualClock::new(Local.with_ymd_and_hms(2025, 6, 1, 11, 20, 0).unwrap()));
+        let mut scheduler = Scheduler::with_clock(Arc::new(RwLock::new(recommender)), Duration::from_secs(60), clock.clone())
+            .with_lookahead(Duration::from_secs(30 * 60));
+
+        // rain at noon is still 40 minutes away
+        assert!(scheduler.refresh().await.unwrap());
+        assert_eq!(paths(&scheduler), ["Ocean.mp3", "Summertime.mp3"]);
+
+        // a quarter of an hour away, half the weather weight goes to the rain already
+        clock.advance(chrono::Duration::minutes(25));
+        assert!(!scheduler.refresh().await.unwrap());
+        assert_eq!(paths(&scheduler), ["MorningRain.mp3", "Rain.mp3", "Ocean.mp3", "Summertime.mp3"]);
+        assert_eq!(scheduler.get_context().unwrap().weather.vibe_name(), "cloudy");
+    }
+
+    // replays the rain-at-noon forecast until taken offline
+    struct Unreliable {
+        provider: FileWeatherProvider,
+        offline: AtomicBool,
+    }
+
+    #[async_trait]
+    impl WeatherProvider for Unreliable {
+        async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+            Ok(self.get_weather_and_forecast().await?.0)
+        }
+
+        async fn get_weather_and_forecast(&self) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+            if self.offline.load(Ordering::SeqCst) {
+                return Err(VibingError::Parse("offline".to_string()));
+            }
+            self.provider.get_weather_and_forecast().await
+        }
+    }
+
+    #[tokio::test]
+    async fn forgets_the_forecast_while_the_weather_is_unavailable() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecast/wttr_rain_at_noon.json");
+        let provider = Arc::new(Unreliable { provider: FileWeatherProvider::new(path).unwrap(), offline: AtomicBool::new(false) });
+        let recommender = Recommender::new(Arc::new(RwLock::new(database)), TimeData::default(), provider.clone());
+        let clock = Arc::new(ManualClock::new(Local.with_ymd_and_hms(2025, 6, 1, 11, 45, 0).unwrap()));
+        let mut scheduler = Scheduler::with_clock(Arc::new(RwLock::new(recommender)), Duration::from_secs(60), clock.clone())
+            .with_lookahead(Duration::from_secs(30 * 60));
+
+        assert!(scheduler.refresh().await.unwrap());
+        assert!(paths(&scheduler).contains(&"Rain.mp3"));
+
+        provider.offline.store(true, Ordering::SeqCst);
+        assert!(!scheduler.refresh().await.unwrap());
+        assert_eq!(paths(&scheduler), ["Ocean.mp3", "Summertime.mp3"]);
+        assert_eq!(scheduler.get_context().unwrap().weather.vibe_name(), "cloudy");
+    }
+
+    #[tokio::test]
+    async fn shifts_toward_the_forecast_weather_refresh_by_refresh() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecast/wttr_rain_at_noon.json");
+        let recommender = Recommender::new(Arc::new(RwLock::new(database)), TimeData::default(), Arc::new(FileWeatherProvider::new(path).unwrap()));
+        let clock = Arc::new(ManualClock::new(Local.with_ymd_and_hms(2025, 6, 1, 11, 20, 0).unwrap()));
+        let interval = Duration::from_secs(300);
+        let mut scheduler = Scheduler::with_clock(Arc::new(RwLock::new(recommender)), interval, clock.clone())
+            .with_lookahead(Duration::from_secs(1800));
+
+        let mut queues = Vec::new();
+        scheduler.refresh().await.unwrap();
+        for _ in 0..7 {
+            clock.advance(chrono::Duration::from_std(interval).unwrap());
+            assert!(!scheduler.refresh().await.unwrap());
+            queues.push(paths(&scheduler).join(" "));
+        }
+
+        // rain at noon comes into view at 11:30, the rainy tracks join the queue and then lead it
+        let (calm, joined, leading) = ("Ocean.mp3 Summertime.mp3", "Ocean.mp3 Summertime.mp3 MorningRain.mp3 Rain.mp3", "MorningRain.mp3 Rain.mp3 Ocean.mp3 Summertime.mp3");
+        assert_eq!(queues, [calm, calm, joined, joined, leading, leading, leading]);
+    }
+}
@@ -0,0 +1,91 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+// sun altitudes in degrees, sunrise/sunset account for refraction and the size of the disc
+pub const SUNRISE_ALTITUDE: f64 = -0.833;
+pub const CIVIL_TWILIGHT_ALTITUDE: f64 = -6.0;
+pub const ASTRONOMICAL_TWILIGHT_ALTITUDE: f64 = -18.0;
+
+const J2000: f64 = 2_451_545.0;
+const UNIX_EPOCH_JULIAN: f64 = 2_440_587.5;
+const OBLIQUITY: f64 = 23.4397;
+
+// when the sun crosses some altitudes on a given day, None when it never gets there (polar day or night)
+#[derive(Debug, Clone, Copy)]
+pub struct SunTimes {
+    pub astronomical_dawn: Option<DateTime<Utc>>,
+    pub civil_dawn: Option<DateTime<Utc>>,
+    pub sunrise: Option<DateTime<Utc>>,
+    pub noon: DateTime<Utc>,
+    pub sunset: Option<DateTime<Utc>>,
+    pub civil_dusk: Option<DateTime<Utc>>,
+    pub astronomical_dusk: Option<DateTime<Utc>>,
+}
+
+// the sunrise equation, good to a minute or two away from the poles. longitude is east positive
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> SunTimes {
+    let days = (date - NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()).num_days() as f64 + 0.0008;
+    let mean_solar_time = days - longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0).to_radians();
+    let center = 1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+
+    let transit = J2000 + mean_solar_time + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+    let declination = (ecliptic_longitude.sin() * OBLIQUITY.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+
+    // half the time the sun spends above the altitude, in days
+    let half_arc = |altitude: f64| {
+        let cos_hour_angle = (altitude.to_radians().sin() - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        (-1.0..=1.0).contains(&cos_hour_angle).then(|| cos_hour_angle.acos().to_degrees() / 360.0)
+    };
+    let rising = |altitude: f64| half_arc(altitude).and_then(|arc| from_julian(transit - arc));
+    let setting = |altitude: f64| half_arc(altitude).and_then(|arc| from_julian(transit + arc));
+
+    SunTimes {
+        astronomical_dawn: rising(ASTRONOMICAL_TWILIGHT_ALTITUDE),
+        civil_dawn: rising(CIVIL_TWILIGHT_ALTITUDE),
+        sunrise: rising(SUNRISE_ALTITUDE),
+        noon: from_julian(transit).unwrap(),
+        sunset: setting(SUNRISE_ALTITUDE),
+        civil_dusk: setting(CIVIL_TWILIGHT_ALTITUDE),
+        astronomical_dusk: setting(ASTRONOMICAL_TWILIGHT_ALTITUDE),
+    }
+}
+
+fn from_julian(julian: f64) -> Option<DateTime<Utc>> {
+    let millis = ((julian - UNIX_EPOCH_JULIAN) * 86_400_000.0).round() as i64;
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Option<DateTime<Utc>>, expected: &str) {
+        let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+        let off = (actual.unwrap() - expected.with_timezone(&Utc)).num_seconds().abs();
+        assert!(off <= 150, "{actual:?} is {off}s away from {expected}");
+    }
+
+    #[test]
+    fn hanoi_at_the_june_solstice() {
+        let sun = sun_times(NaiveDate::from_ymd_opt(2025, 6, 21).unwrap(), 21.0285, 105.8542);
+
+        assert_near(sun.sunrise, "2025-06-21T05:15:00+07:00");
+        assert_near(Some(sun.noon), "2025-06-21T11:58:00+07:00");
+        assert_near(sun.sunset, "2025-06-21T18:42:00+07:00");
+        let order = [sun.astronomical_dawn, sun.civil_dawn, sun.sunrise, Some(sun.noon), sun.sunset, sun.civil_dusk, sun.astronomical_dusk];
+        assert!(order.windows(2).all(|pair| pair[0].unwrap() < pair[1].unwrap()));
+    }
+
+    #[test]
+    fn no_sunset_in_the_arctic_summer() {
+        let sun = sun_times(NaiveDate::from_ymd_opt(2025, 6, 21).unwrap(), 69.6492, 18.9553);
+
+        assert!(sun.sunrise.is_none() && sun.sunset.is_none());
+        assert!(sun.astronomical_dusk.is_none());
+        assert_near(Some(sun.noon), "2025-06-21T10:46:00+00:00");
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use crate::{configuration::WeatherMappingConfig, data_collector::{Temperature, Weather}};
+
+// wttr.in `weatherCode`s (worldweatheronline conditions) with the english text they come with
+const WTTR_CODES: [(u16, &str, &str); 48] = [
+    (113, "Sunny", "sunny"),
+    (116, "Partly cloudy", "cloudy"),
+    (119, "Cloudy", "cloudy"),
+    (122, "Overcast", "cloudy"),
+    (143, "Mist", "foggy"),
+    (176, "Patchy rain possible", "rainy"),
+    (179, "Patchy snow possible", "snowy"),
+    (182, "Patchy sleet possible", "rainy"),
+    (185, "Patchy freezing drizzle possible", "rainy"),
+    (200, "Thundery outbreaks possible", "stormy"),
+    (227, "Blowing snow", "snowy"),
+    (230, "Blizzard", "stormy"),
+    (248, "Fog", "foggy"),
+    (260, "Freezing fog", "foggy"),
+    (263, "Patchy light drizzle", "rainy"),
+    (266, "Light drizzle", "rainy"),
+    (281, "Freezing drizzle", "rainy"),
+    (284, "Heavy freezing drizzle", "rainy"),
+    (293, "Patchy light rain", "rainy"),
+    (296, "Light rain", "rainy"),
+    (299, "Moderate rain at times", "rainy"),
+    (302, "Moderate rain", "rainy"),
+    (305, "Heavy rain at times", "rainy"),
+    (308, "Heavy rain", "rainy"),
+    (311, "Light freezing rain", "rainy"),
+    (314, "Moderate or heavy freezing rain", "rainy"),
+    (317, "Light sleet", "rainy"),
+    (320, "Moderate or heavy sleet", "rainy"),
+    (323, "Patchy light snow", "snowy"),
+    (326, "Light snow", "snowy"),
+    (329, "Patchy moderate snow", "snowy"),
+    (332, "Moderate snow", "snowy"),
+    (335, "Patchy heavy snow", "snowy"),
+    (338, "Heavy snow", "snowy"),
+    (350, "Ice pellets", "snowy"),
+    (353, "Light rain shower", "rainy"),
+    (356, "Moderate or heavy rain shower", "rainy"),
+    (359, "Torrential rain shower", "rainy"),
+    (362, "Light sleet showers", "rainy"),
+    (365, "Moderate or heavy sleet showers", "rainy"),
+    (368, "Light snow showers", "snowy"),
+    (371, "Moderate or heavy snow showers", "snowy"),
+    (374, "Light showers of ice pellets", "snowy"),
+    (377, "Moderate or heavy showers of ice pellets", "snowy"),
+    (386, "Patchy light rain with thunder", "stormy"),
+    (389, "Moderate or heavy rain with thunder", "stormy"),
+    (392, "Patchy light snow with thunder", "stormy"),
+    (395, "Moderate or heavy snow with thunder", "stormy"),
+];
+
+// WMO weather interpretation codes, as open-meteo sends them
+const WMO_CODES: [(u16, &str, &str); 28] = [
+    (0, "Clear sky", "sunny"),
+    (1, "Mainly clear", "cloudy"),
+    (2, "Partly cloudy", "cloudy"),
+    (3, "Overcast", "cloudy"),
+    (45, "Fog", "foggy"),
+    (48, "Depositing rime fog", "foggy"),
+    (51, "Light drizzle", "rainy"),
+    (53, "Moderate drizzle", "rainy"),
+    (55, "Dense drizzle", "rainy"),
+    (56, "Light freezing drizzle", "rainy"),
+    (57, "Dense freezing drizzle", "rainy"),
+    (61, "Slight rain", "rainy"),
+    (63, "Moderate rain", "rainy"),
+    (65, "Heavy rain", "rainy"),
+    (66, "Light freezing rain", "rainy"),
+    (67, "Heavy freezing rain", "rainy"),
+    (71, "Slight snow fall", "snowy"),
+    (73, "Moderate snow fall", "snowy"),
+    (75, "Heavy snow fall", "snowy"),
+    (77, "Snow grains", "snowy"),
+    (80, "Slight rain showers", "rainy"),
+    (81, "Moderate rain showers", "rainy"),
+    (82, "Violent rain showers", "rainy"),
+    (85, "Slight snow showers", "snowy"),
+    (86, "Heavy snow showers", "snowy"),
+    (95, "Thunderstorm", "stormy"),
+    (96, "Thunderstorm with slight hail", "stormy"),
+    (99, "Thunderstorm with heavy hail", "stormy"),
+];
+
+// words looked for in a description that is in no table, the longest one found wins
+const KEYWORDS: [(&str, &str); 16] = [
+    ("thunder", "stormy"),
+    ("blizzard", "stormy"),
+    ("snow shower", "snowy"),
+    ("snow", "snowy"),
+    ("ice pellets", "snowy"),
+    ("sleet", "rainy"),
+    ("rain", "rainy"),
+    ("drizzle", "rainy"),
+    ("shower", "rainy"),
+    ("fog", "foggy"),
+    ("mist", "foggy"),
+    ("haze", "foggy"),
+    ("overcast", "cloudy"),
+    ("cloud", "cloudy"),
+    ("sunny", "sunny"),
+    ("clear", "sunny"),
+];
+
+// turns a provider's condition code, or failing that its description, into a weather vibe
+#[derive(Debug, Clone)]
+pub struct WeatherMapping {
+    codes: HashMap<u16, String>,
+    // lowercased
+    descriptions: HashMap<String, String>,
+}
+
+impl WeatherMapping {
+    pub fn wttr() -> Self {
+        Self::from_table(&WTTR_CODES)
+    }
+
+    pub fn open_meteo() -> Self {
+        Self::from_table(&WMO_CODES)
+    }
+
+    fn from_table(codes: &[(u16, &str, &str)]) -> Self {
+        Self {
+            codes: codes.iter().map(|(code, _, vibe)| (*code, vibe.to_string())).collect(),
+            descriptions: codes.iter().map(|(_, description, vibe)| (description.to_lowercase(), vibe.to_string())).collect(),
+        }
+    }
+
+    // weather.mapping from the config, on top of the built-in table
+    pub fn with_overrides(mut self, overrides: &WeatherMappingConfig) -> Self {
+        self.codes.extend(overrides.codes.iter().map(|(code, vibe)| (*code, vibe.clone())));
+        self.descriptions.extend(overrides.descriptions.iter()
+            .map(|(description, vibe)| (description.trim().to_lowercase(), vibe.clone())));
+        self
+    }
+
+    pub fn get_weather(&self, code: Option<u16>, description: &str, temp: Temperature) -> Weather {
+        if let Some(code) = code {
+            if let Some(weather) = self.codes.get(&code).and_then(|vibe| Weather::from_vibe(vibe, temp)) {
+                return weather;
+            }
+            eprintln!("unknown weather code {code} ({description:?})");
+        }
+
+        let description = description.trim().to_lowercase();
+        let vibe = self.descriptions.get(&description).map(String::as_str).or_else(|| {
+            KEYWORDS.iter()
+                .filter(|(keyword, _)| description.contains(keyword))
+                .max_by_key(|(keyword, _)| keyword.len())
+                .map(|(_, vibe)| *vibe)
+        });
+
+        vibe.and_then(|vibe| Weather::from_vibe(vibe, temp)).unwrap_or(Weather::Default(temp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vibe_of(mapping: &WeatherMapping, code: Option<u16>, description: &str) -> &'static str {
+        mapping.get_weather(code, description, 20.0).vibe_name()
+    }
+
+    #[test]
+    fn codes_come_before_descriptions() {
+        let mapping = WeatherMapping::wttr();
+
+        assert_eq!(vibe_of(&mapping, Some(338), "Heavy snow"), "snowy");
+        assert_eq!(vibe_of(&mapping, Some(143), "Brume"), "foggy");
+        assert_eq!(vibe_of(&mapping, Some(113), "Clear"), "sunny");
+        assert_eq!(vibe_of(&WeatherMapping::open_meteo(), Some(45), ""), "foggy");
+    }
+
+    #[test]
+    fn falls_back_to_the_description() {
+        let mapping = WeatherMapping::wttr();
+
+        assert_eq!(vibe_of(&mapping, Some(999), "Patchy rain nearby"), "rainy");
+        assert_eq!(vibe_of(&mapping, None, "Light rain, thunderstorm in vicinity"), "stormy");
+        assert_eq!(vibe_of(&mapping, None, "Heavy snow showers"), "snowy");
+        assert_eq!(vibe_of(&mapping, Some(999), "Volcanic ash"), "none");
+    }
+
+    #[test]
+    fn config_overrides_the_table() {
+        let overrides = WeatherMappingConfig {
+            codes: HashMap::from([(230, "snowy".to_string()), (999, "windy".to_string())]),
+            descriptions: HashMap::from([("sandstorm".to_string(), "windy".to_string())]),
+        };
+        let mapping = WeatherMapping::wttr().with_overrides(&overrides);
+
+        assert_eq!(vibe_of(&mapping, Some(230), "Blizzard"), "snowy");
+        assert_eq!(vibe_of(&mapping, Some(999), ""), "windy");
+        assert_eq!(vibe_of(&mapping, None, "Sandstorm"), "windy");
+        assert_eq!(vibe_of(&mapping, Some(296), "Light rain"), "rainy");
+    }
+}
@@ -0,0 +1,556 @@
+use std::{fs, io, path::Path, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::{configuration::{HttpConfig, WeatherConfig, WeatherMappingConfig, WeatherProviderKind, WeatherThresholds}, data_collector::{ApiClient, ForecastHour, WeatherData, WeatherSet}, database::Mp3Database, error::VibingError, weather_mapping::WeatherMapping};
+
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    async fn get_weather(&self) -> Result<WeatherSet, VibingError>;
+
+    // what's coming, oldest first. providers without a forecast have none
+    async fn get_forecast(&self) -> Result<Vec<ForecastHour>, VibingError> {
+        Ok(Vec::new())
+    }
+
+    // both at once, from a single response where the provider sends them together
+    async fn get_weather_and_forecast(&self) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+        Ok((self.get_weather().await?, self.get_forecast().await?))
+    }
+}
+
+// the network providers go through the weather cache in the database
+pub fn from_config(config: &WeatherConfig, database: Arc<RwLock<Mp3Database>>) -> Result<Arc<dyn WeatherProvider>, VibingError> {
+    let cached = |provider: Arc<dyn WeatherProvider>, source: String| -> Arc<dyn WeatherProvider> {
+        Arc::new(CachedWeatherProvider::new(provider, database.clone(), &source)
+            .with_ttl(Duration::from_secs(config.cache_ttl_secs))
+            .with_max_staleness(Duration::from_secs(config.max_staleness_secs)))
+    };
+
+    let provider: Arc<dyn WeatherProvider> = match config.provider {
+        WeatherProviderKind::Wttr => cached(
+            Arc::new(WeatherData::new(config.clone())),
+            format!("wttr {} {}", config.host, config.city),
+        ),
+        WeatherProviderKind::OpenMeteo => cached(
+            Arc::new(OpenMeteoProvider::new(&config.open_meteo_host, config.latitude, config.longitude)
+                .with_thresholds(config.thresholds.clone())
+                .with_mapping(&config.mapping)
+                .with_http(config.http.clone())),
+            format!("open_meteo {} {},{}", config.open_meteo_host, config.latitude, config.longitude),
+        ),
+        WeatherProviderKind::Fixed => Arc::new(FixedWeather::new(
+            WeatherSet::from_vibes(&config.fixed_weather, config.fixed_temperature)
+                .unwrap_or(WeatherSet::new(config.fixed_temperature))
+        )),
+        WeatherProviderKind::File => Arc::new(
+            FileWeatherProvider::new(&config.replay_path)?
+                .with_thresholds(config.thresholds.clone())
+                .with_mapping(&config.mapping)
+        ),
+    };
+
+    Ok(provider)
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoCurrent {
+    temperature_2m: f32,
+    relative_humidity_2m: Option<f32>,
+    wind_speed_10m: Option<f32>,
+    weather_code: u16,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoResponse {
+    current: OpenMeteoCurrent,
+}
+
+pub struct OpenMeteoProvider {
+    host: String,
+    latitude: f64,
+    longitude: f64,
+    thresholds: WeatherThresholds,
+    mapping: WeatherMapping,
+    client: ApiClient,
+}
+
+impl OpenMeteoProvider {
+    pub fn new(host: &str, latitude: f64, longitude: f64) -> Self {
+        Self {
+            host: host.trim_end_matches('/').to_string(),
+            latitude,
+            longitude,
+            thresholds: WeatherThresholds::default(),
+            mapping: WeatherMapping::open_meteo(),
+            client: ApiClient::new(HttpConfig::default()),
+        }
+    }
+
+    pub fn with_thresholds(mut self, thresholds: WeatherThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn with_mapping(mut self, overrides: &WeatherMappingConfig) -> Self {
+        self.mapping = WeatherMapping::open_meteo().with_overrides(overrides);
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpConfig) -> Self {
+        self.client = ApiClient::new(http);
+        self
+    }
+
+    // parse an open-meteo `current` payload, codes are WMO weather interpretation codes
+    pub fn parse_weather(response: &str, mapping: &WeatherMapping, thresholds: &WeatherThresholds) -> Result<WeatherSet, VibingError> {
+        let weather_data: OpenMeteoResponse = serde_json::from_str(response)?;
+        let current = weather_data.current;
+        let temp = current.temperature_2m;
+
+        let sky = mapping.get_weather(Some(current.weather_code), "", temp);
+
+        Ok(WeatherSet::classify(sky, current.relative_humidity_2m, current.wind_speed_10m, thresholds))
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteoProvider {
+    async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+        let url = format!(
+            "{}/v1/forecast?latitude={}&longitude={}&current=temperature_2m,relative_humidity_2m,wind_speed_10m,weather_code",
+            self.host, self.latitude, self.longitude
+        );
+        let response = self.client.get(&url).await?;
+
+        Self::parse_weather(&response, &self.mapping, &self.thresholds)
+    }
+}
+
+// answers from the weather_cache table while the last observation is fresh and asks the provider
+// once it's older than the ttl. while the provider fails the last observation stands in until it
+// is older than max_staleness, then the weather is none until the provider is back.
+// the forecast is fetched along with the weather and expires with it
+pub struct CachedWeatherProvider {
+    provider: Arc<dyn WeatherProvider>,
+    database: Arc<RwLock<Mp3Database>>,
+    source: String,
+    ttl: Duration,
+    max_staleness: Duration,
+    // forecasts are only kept in memory, a stale one is worse than none
+    forecast: Mutex<Option<(DateTime<Local>, Vec<ForecastHour>)>>,
+}
+
+impl CachedWeatherProvider {
+    pub fn new(provider: Arc<dyn WeatherProvider>, database: Arc<RwLock<Mp3Database>>, source: &str) -> Self {
+        let config = WeatherConfig::default();
+        Self {
+            provider,
+            database,
+            source: source.to_string(),
+            ttl: Duration::from_secs(config.cache_ttl_secs),
+            max_staleness: Duration::from_secs(config.max_staleness_secs),
+            forecast: Mutex::new(None),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+
+    pub async fn get_weather_at(&self, now: DateTime<Local>) -> Result<WeatherSet, VibingError> {
+        let cached = self.database.read().await.get_cached_weather(&self.source).await?;
+        if let Some((weather, fetched_at)) = cached && Self::age(fetched_at, now) < self.ttl {
+            return Ok(weather);
+        }
+
+        Ok(self.fetch_at(now, cached).await.0)
+    }
+
+    pub async fn get_weather_and_forecast_at(&self, now: DateTime<Local>) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+        let cached = self.database.read().await.get_cached_weather(&self.source).await?;
+        let Some((weather, fetched_at)) = cached.filter(|(_, fetched_at)| Self::age(*fetched_at, now) < self.ttl) else {
+            return Ok(self.fetch_at(now, cached).await);
+        };
+
+        let forecast = self.forecast.lock().unwrap().clone();
+        if let Some((forecast_fetched_at, forecast)) = forecast && Self::age(forecast_fetched_at, now) < self.ttl {
+            return Ok((weather, forecast));
+        }
+
+        // the weather outlives a restart, the forecast doesn't
+        match self.provider.get_forecast().await {
+            Ok(forecast) => {
+                *self.forecast.lock().unwrap() = Some((fetched_at, forecast.clone()));
+                Ok((weather, forecast))
+            }
+            Err(e) => {
+                eprintln!("cannot get forecast: {e}");
+                *self.forecast.lock().unwrap() = None;
+                Ok((weather, Vec::new()))
+            }
+        }
+    }
+
+    // asks the provider for both, falling back on the cached weather and no forecast when it fails
+    async fn fetch_at(&self, now: DateTime<Local>, cached: Option<(WeatherSet, DateTime<Local>)>) -> (WeatherSet, Vec<ForecastHour>) {
+        match self.provider.get_weather_and_forecast().await {
+            Ok((weather, forecast)) => {
+                if let Err(e) = self.database.read().await.cache_weather(&self.source, &weather, now).await {
+                    eprintln!("cannot cache the weather: {e}");
+                }
+                *self.forecast.lock().unwrap() = Some((now, forecast.clone()));
+                (weather, forecast)
+            }
+            Err(e) => {
+                *self.forecast.lock().unwrap() = None;
+                let weather = match cached {
+                    Some((weather, fetched_at)) if Self::age(fetched_at, now) <= self.max_staleness => {
+                        eprintln!("cannot get weather, keeping the one from {}: {e}", fetched_at.format("%H:%M"));
+                        weather
+                    }
+                    _ => {
+                        eprintln!("cannot get weather and nothing recent is cached: {e}");
+                        WeatherSet::new(0.0)
+                    }
+                };
+                (weather, Vec::new())
+            }
+        }
+    }
+
+    fn age(fetched_at: DateTime<Local>, now: DateTime<Local>) -> Duration {
+        (now - fetched_at).to_std().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for CachedWeatherProvider {
+    async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+        self.get_weather_at(Local::now()).await
+    }
+
+    async fn get_forecast(&self) -> Result<Vec<ForecastHour>, VibingError> {
+        Ok(self.get_weather_and_forecast_at(Local::now()).await?.1)
+    }
+
+    async fn get_weather_and_forecast(&self) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+        self.get_weather_and_forecast_at(Local::now()).await
+    }
+}
+
+// always answers with the weather it was told, handy offline or to force a vibe
+pub struct FixedWeather {
+    weather: Mutex<WeatherSet>,
+}
+
+impl FixedWeather {
+    pub fn new(weather: impl Into<WeatherSet>) -> Self {
+        Self { weather: Mutex::new(weather.into()) }
+    }
+
+    pub fn set(&self, weather: impl Into<WeatherSet>) {
+        *self.weather.lock().unwrap() = weather.into();
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for FixedWeather {
+    async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+        Ok(*self.weather.lock().unwrap())
+    }
+}
+
+// replays recorded wttr.in or open-meteo responses, one per call, looping at the end
+pub struct FileWeatherProvider {
+    responses: Vec<String>,
+    next: AtomicUsize,
+    thresholds: WeatherThresholds,
+    wttr: WeatherMapping,
+    open_meteo: WeatherMapping,
+}
+
+impl FileWeatherProvider {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, VibingError> {
+        let path = path.as_ref();
+
+        let responses = if path.is_dir() {
+            let mut files: Vec<_> = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            files.sort();
+            files.iter().map(fs::read_to_string).collect::<Result<Vec<_>, _>>()?
+        } else {
+            vec![fs::read_to_string(path)?]
+        };
+
+        if responses.is_empty() {
+            return Err(VibingError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no recorded responses in {}", path.display()),
+            )));
+        }
+
+        Ok(Self {
+            responses,
+            next: AtomicUsize::new(0),
+            thresholds: WeatherThresholds::default(),
+            wttr: WeatherMapping::wttr(),
+            open_meteo: WeatherMapping::open_meteo(),
+        })
+    }
+
+    pub fn with_thresholds(mut self, thresholds: WeatherThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn with_mapping(mut self, overrides: &WeatherMappingConfig) -> Self {
+        self.wttr = WeatherMapping::wttr().with_overrides(overrides);
+        self.open_meteo = WeatherMapping::open_meteo().with_overrides(overrides);
+        self
+    }
+
+    fn parse_weather(&self, response: &str) -> Result<WeatherSet, VibingError> {
+        if response.contains("\"current_condition\"") {
+            WeatherData::parse_weather(response, &self.wttr, &self.thresholds)
+        } else {
+            OpenMeteoProvider::parse_weather(response, &self.open_meteo, &self.thresholds)
+        }
+    }
+
+    // only wttr.in responses carry a forecast
+    fn parse_forecast(&self, response: &str) -> Result<Vec<ForecastHour>, VibingError> {
+        if response.contains("\"current_condition\"") {
+            WeatherData::parse_forecast(response, &self.wttr, &self.thresholds)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for FileWeatherProvider {
+    async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.responses.len();
+        self.parse_weather(&self.responses[index])
+    }
+
+    // the forecast of the response last replayed
+    async fn get_forecast(&self) -> Result<Vec<ForecastHour>, VibingError> {
+        let index = self.next.load(Ordering::Relaxed).saturating_sub(1) % self.responses.len();
+        self.parse_forecast(&self.responses[index])
+    }
+
+    async fn get_weather_and_forecast(&self) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.responses.len();
+        let response = &self.responses[index];
+        Ok((self.parse_weather(response)?, self.parse_forecast(response)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_collector::Weather;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/weather");
+
+    #[tokio::test]
+    async fn replays_recorded_responses_in_order() {
+        let provider = FileWeatherProvider::new(FIXTURES).unwrap();
+
+        let weather = provider.get_weather().await.unwrap();
+        assert_eq!((weather.vibe_names(), weather.get_temperature()), (vec!["rainy"], 18.0));
+        let weather = provider.get_weather().await.unwrap();
+        assert_eq!((weather.vibe_names(), weather.get_temperature()), (vec!["stormy"], 31.4));
+        let weather = provider.get_weather().await.unwrap();
+        assert_eq!((weather.vibe_names(), weather.get_temperature()), (vec!["sunny", "hotty"], 33.0));
+        let weather = provider.get_weather().await.unwrap();
+        assert!(matches!(weather.primary(), Weather::Rainy(t) if t == 18.0));
+    }
+
+    #[test]
+    fn reports_an_empty_replay_directory_as_not_found() {
+        let empty = tempfile::tempdir().unwrap();
+
+        let err = FileWeatherProvider::new(empty.path()).err().unwrap();
+        assert!(matches!(err, VibingError::Io(ref e) if e.kind() == io::ErrorKind::NotFound), "{err}");
+        let err = FileWeatherProvider::new(empty.path().join("missing.json")).err().unwrap();
+        assert!(matches!(err, VibingError::Io(ref e) if e.kind() == io::ErrorKind::NotFound), "{err}");
+    }
+
+    #[tokio::test]
+    async fn classifies_with_the_configured_thresholds() {
+        let thresholds = WeatherThresholds { cold_celsius: 20.0, windy_kmph: 8.0, ..WeatherThresholds::default() };
+        let provider = FileWeatherProvider::new(FIXTURES).unwrap().with_thresholds(thresholds);
+
+        // 18C with a 9km/h breeze
+        assert_eq!(provider.get_weather().await.unwrap().vibe_names(), ["rainy", "windy", "coldy"]);
+        // no humidity or wind in the open-meteo fixture
+        assert_eq!(provider.get_weather().await.unwrap().vibe_names(), ["stormy"]);
+    }
+
+    #[test]
+    fn humid_air_is_hot_earlier() {
+        let thresholds = WeatherThresholds::default();
+        let muggy = WeatherSet::classify(Weather::Cloudy(30.0), Some(85.0), Some(5.0), &thresholds);
+        let dry = WeatherSet::classify(Weather::Cloudy(30.0), Some(40.0), Some(5.0), &thresholds);
+
+        assert_eq!(muggy.vibe_names(), ["cloudy", "hotty"]);
+        assert_eq!(dry.vibe_names(), ["cloudy"]);
+        assert_eq!(WeatherSet::classify(Weather::Default(22.0), None, None, &thresholds).vibe_names(), ["none"]);
+    }
+
+    #[test]
+    fn maps_wttr_codes_to_snow_and_fog() {
+        let j1 = |code: &str, description: &str| format!(
+            r#"{{ "current_condition": [{{ "temp_C": "-2", "weatherCode": "{code}", "weatherDesc": [{{ "value": "{description}" }}] }}] }}"#
+        );
+        let parse = |response: String| {
+            WeatherData::parse_weather(&response, &WeatherMapping::wttr(), &WeatherThresholds::default()).unwrap().vibe_names()
+        };
+
+        assert_eq!(parse(j1("338", "Heavy snow")), ["snowy", "coldy"]);
+        assert_eq!(parse(j1("260", "Brouillard givrant")), ["foggy", "coldy"]);
+        assert_eq!(parse(j1("", "Light snow showers")), ["snowy", "coldy"]);
+    }
+
+    // the weather it was told, or an error while offline. its forecast is that weather in an hour
+    struct Flaky {
+        weather: Mutex<Option<WeatherSet>>,
+        requests: AtomicUsize,
+    }
+
+    impl Flaky {
+        fn new(weather: Weather) -> Arc<Self> {
+            Arc::new(Self { weather: Mutex::new(Some(weather.into())), requests: AtomicUsize::new(0) })
+        }
+    }
+
+    #[async_trait]
+    impl WeatherProvider for Flaky {
+        async fn get_weather(&self) -> Result<WeatherSet, VibingError> {
+            self.weather.lock().unwrap().ok_or_else(|| VibingError::Parse("offline".to_string()))
+        }
+
+        async fn get_weather_and_forecast(&self) -> Result<(WeatherSet, Vec<ForecastHour>), VibingError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let weather = self.get_weather().await?;
+            Ok((weather, vec![ForecastHour { start: Local::now() + chrono::Duration::hours(1), weather }]))
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_the_weather_when_it_cannot_be_cached() {
+        let directory = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", directory.path().join("vibing.sqlite").display());
+        let database = Mp3Database::new(&format!("{url}?mode=rwc")).await.unwrap();
+        database.migrate().await.unwrap();
+        drop(database);
+        let read_only = Mp3Database::new(&format!("{url}?mode=ro")).await.unwrap();
+        let flaky = Flaky::new(Weather::Rainy(18.0));
+        let provider = CachedWeatherProvider::new(flaky, Arc::new(RwLock::new(read_only)), "test");
+
+        assert_eq!(provider.get_weather().await.unwrap().vibe_names(), ["rainy"]);
+    }
+
+    #[tokio::test]
+    async fn caches_and_falls_back_to_the_last_good_weather() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let database = Arc::new(RwLock::new(database));
+        let flaky = Flaky::new(Weather::Rainy(18.0));
+        let provider = CachedWeatherProvider::new(flaky.clone(), database.clone(), "test")
+            .with_ttl(Duration::from_secs(10 * 60))
+            .with_max_staleness(Duration::from_secs(60 * 60));
+        let start = Local::now();
+        let vibes_at = async |provider: &CachedWeatherProvider, minutes: i64| {
+            provider.get_weather_at(start + chrono::Duration::minutes(minutes)).await.unwrap().vibe_names()
+        };
+        let set = |weather: Option<Weather>| *flaky.weather.lock().unwrap() = weather.map(WeatherSet::from);
+
+        assert_eq!(vibes_at(&provider, 0).await, ["rainy"]);
+        set(Some(Weather::Sunny(30.0)));
+        assert_eq!(vibes_at(&provider, 5).await, ["rainy"]);
+        assert_eq!(vibes_at(&provider, 15).await, ["sunny"]);
+
+        // offline, and after a restart the cache is still there
+        set(None);
+        let provider = CachedWeatherProvider::new(flaky.clone(), database, "test")
+            .with_max_staleness(Duration::from_secs(60 * 60));
+        assert_eq!(vibes_at(&provider, 20).await, ["sunny"]);
+        assert_eq!(vibes_at(&provider, 60).await, ["sunny"]);
+        assert_eq!(vibes_at(&provider, 120).await, ["none"]);
+
+        set(Some(Weather::Cloudy(25.0)));
+        assert_eq!(vibes_at(&provider, 125).await, ["cloudy"]);
+    }
+
+    #[tokio::test]
+    async fn serves_the_cached_weather_after_a_restart() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let database = Arc::new(RwLock::new(database));
+        let start = Local::now();
+        let provider = CachedWeatherProvider::new(Flaky::new(Weather::Rainy(18.0)), database.clone(), "test")
+            .with_ttl(Duration::from_secs(10 * 60));
+        provider.get_weather_at(start).await.unwrap();
+
+        // the cache answers within the ttl, the offline provider isn't asked for the weather
+        let offline = Flaky::new(Weather::Sunny(30.0));
+        *offline.weather.lock().unwrap() = None;
+        let provider = CachedWeatherProvider::new(offline.clone(), database, "test")
+            .with_ttl(Duration::from_secs(10 * 60))
+            .with_max_staleness(Duration::ZERO);
+        let later = start + chrono::Duration::minutes(5);
+        assert_eq!(provider.get_weather_at(later).await.unwrap().vibe_names(), ["rainy"]);
+        let (weather, forecast) = provider.get_weather_and_forecast_at(later).await.unwrap();
+        assert_eq!((weather.vibe_names(), forecast.len()), (vec!["rainy"], 0));
+        assert_eq!(offline.requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn caches_the_forecast_with_the_weather() {
+        let database = Mp3Database::new("sqlite::memory:").await.unwrap();
+        database.migrate().await.unwrap();
+        let flaky = Flaky::new(Weather::Rainy(18.0));
+        let provider = CachedWeatherProvider::new(flaky.clone(), Arc::new(RwLock::new(database)), "test")
+            .with_ttl(Duration::from_secs(10 * 60));
+        let start = Local::now();
+        let at = async |minutes: i64| {
+            let (weather, forecast) = provider.get_weather_and_forecast_at(start + chrono::Duration::minutes(minutes)).await.unwrap();
+            (weather.vibe_name(), forecast.iter().map(|hour| hour.weather.vibe_name()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(at(0).await, ("rainy".to_string(), vec!["rainy".to_string()]));
+        assert_eq!(at(5).await, ("rainy".to_string(), vec!["rainy".to_string()]));
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 1);
+
+        // offline, the cached weather stands in but the forecast is gone
+        *flaky.weather.lock().unwrap() = None;
+        assert_eq!(at(15).await, ("rainy".to_string(), Vec::new()));
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn fixed_weather_can_be_changed() {
+        let provider = FixedWeather::new(Weather::Sunny(30.0));
+        assert!(matches!(provider.get_weather().await.unwrap().primary(), Weather::Sunny(_)));
+
+        provider.set(WeatherSet::from_vibes("rainy,coldy", 8.0).unwrap());
+        let weather = provider.get_weather().await.unwrap();
+        assert!(weather.contains(Weather::Rainy(0.0)) && weather.contains(Weather::Coldy(0.0)));
+        assert_eq!(weather.vibe_name(), "rainy,coldy");
+    }
+}